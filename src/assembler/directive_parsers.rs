@@ -0,0 +1,96 @@
+use nom::alpha1;
+use nom::types::CompleteStr;
+
+use super::label_parsers::label_declaration;
+use super::opcode::Token;
+use super::operand_parser::string_operand;
+use super::AssemblerError;
+
+/// A directive placing data in the read-only section, such as `hello: .asciiz 'Hello!'`.
+#[derive(Debug, PartialEq)]
+pub struct AssemblerDirective {
+    label: Option<Token>,
+    directive: Token,
+    operand: Option<Token>,
+}
+
+impl AssemblerDirective {
+    /// Encodes the data the directive places in the read-only section.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AssemblerError> {
+        let name = match &self.directive {
+            Token::Directive { name } => name,
+            _ => return Err(AssemblerError::OpcodeExpected),
+        };
+        match (name.as_str(), &self.operand) {
+            ("asciiz", Some(Token::StringOperand { value })) => {
+                let mut bytes = value.as_bytes().to_vec();
+                bytes.push(0);
+                Ok(bytes)
+            }
+            ("asciiz", _) => Err(AssemblerError::InvalidDirectiveOperand { name: name.clone() }),
+            _ => Err(AssemblerError::UnknownDirective { name: name.clone() }),
+        }
+    }
+
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.label {
+            Some(Token::LabelDeclaration { name }) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+named!(directive_declaration<CompleteStr, Token>,
+    do_parse!(
+        tag!(".") >>
+        name: alpha1 >>
+        (
+            Token::Directive{name: name.to_string()}
+        )
+    )
+);
+
+named!(pub directive<CompleteStr, AssemblerDirective>,
+    ws!(
+        do_parse!(
+            l: opt!(label_declaration) >>
+            d: directive_declaration >>
+            o: opt!(string_operand) >>
+            (
+                AssemblerDirective{
+                    label: l,
+                    directive: d,
+                    operand: o
+                }
+            )
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directive() {
+        let result = directive(CompleteStr("hello: .asciiz 'Hi'\nhlt"));
+        let (rest, parsed) = result.unwrap();
+        assert_eq!(rest, CompleteStr("hlt"));
+        assert_eq!(parsed.label_name(), Some("hello"));
+        assert_eq!(parsed.to_bytes(), Ok(vec![b'H', b'i', 0]));
+    }
+
+    #[test]
+    fn test_directive_errors() {
+        let (_, parsed) = directive(CompleteStr(".asciiz")).unwrap();
+        assert_eq!(
+            parsed.to_bytes(),
+            Err(AssemblerError::InvalidDirectiveOperand { name: "asciiz".to_string() })
+        );
+        let (_, parsed) = directive(CompleteStr(".word 'a'")).unwrap();
+        assert_eq!(
+            parsed.to_bytes(),
+            Err(AssemblerError::UnknownDirective { name: "word".to_string() })
+        );
+    }
+}
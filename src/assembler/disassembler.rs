@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::instruction::{Opcode, Operand};
+
+/// An operand decoded from bytecode, printed the way the assembler reads it back.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DecodedOperand {
+    Register(u8),
+    FloatRegister(u8),
+    Integer(i32),
+    Float(f64),
+    /// A native function, by name when it is known.
+    Native { index: u16, name: Option<String> },
+    /// An address or read-only offset a label was generated for.
+    Label(String),
+}
+
+impl fmt::Display for DecodedOperand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodedOperand::Register(reg) | DecodedOperand::FloatRegister(reg) => write!(f, "${}", reg),
+            DecodedOperand::Integer(value) => write!(f, "#{}", value),
+            DecodedOperand::Float(value) => write!(f, "#{:?}", value),
+            DecodedOperand::Native { name: Some(name), .. } => write!(f, "@{}", name),
+            DecodedOperand::Native { index, name: None } => write!(f, "#{}", index),
+            DecodedOperand::Label(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+/// One instruction decoded from bytecode.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DecodedInstruction {
+    pub opcode: Opcode,
+    pub operands: Vec<DecodedOperand>,
+    /// Number of bytes the instruction takes in the program.
+    pub size: usize,
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        for operand in &self.operands {
+            write!(f, " {}", operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the instruction starting at `pc` in `program`. `natives` are the names of the
+/// registered natives in index order, used to name the function a CALLN calls.
+///
+/// Returns `None` when `pc` is past the end of the program or the instruction is cut short.
+pub fn disassemble(program: &[u8], pc: usize, natives: &[String]) -> Option<DecodedInstruction> {
+    let opcode = Opcode::from(*program.get(pc)?);
+    let mut cursor = pc + 1;
+    let mut operands = vec![];
+    for operand in opcode.operands() {
+        let bytes = program.get(cursor..cursor + operand.size())?;
+        cursor += operand.size();
+        let short = ((bytes[0] as u16) << 8) | *bytes.get(1).unwrap_or(&0) as u16;
+        match operand {
+            Operand::Register => operands.push(DecodedOperand::Register(bytes[0])),
+            Operand::FloatRegister => operands.push(DecodedOperand::FloatRegister(bytes[0])),
+            Operand::Immediate | Operand::Address | Operand::RoOffset | Operand::Syscall => {
+                operands.push(DecodedOperand::Integer(short as i32))
+            }
+            Operand::SignedImmediate | Operand::Offset => {
+                operands.push(DecodedOperand::Integer(short as i16 as i32))
+            }
+            Operand::Float => {
+                let mut float = [0; 8];
+                float.copy_from_slice(bytes);
+                operands.push(DecodedOperand::Float(f64::from_be_bytes(float)));
+            }
+            Operand::Native => {
+                let name = natives.get(short as usize).cloned();
+                operands.push(DecodedOperand::Native { index: short, name });
+                let argc = bytes[2] as usize;
+                let args = program.get(cursor..cursor + argc)?;
+                cursor += argc;
+                operands.extend(args.iter().map(|&reg| DecodedOperand::Register(reg)));
+            }
+        }
+    }
+    Some(DecodedInstruction {
+        opcode,
+        operands,
+        size: cursor - pc,
+    })
+}
+
+/// Turns a program and its read-only data back into source the assembler accepts.
+///
+/// Jump targets and strings get generated labels, `label_<address>` and
+/// `string_<offset>`, so the source still works when assembled at another address. Returns
+/// the address of the first instruction that can't be decoded if there is one.
+pub fn disassemble_program(
+    program: &[u8],
+    ro_data: &[u8],
+    natives: &[String],
+) -> Result<String, usize> {
+    let mut instructions = vec![];
+    let mut pc = 0;
+    while pc < program.len() {
+        let instruction = disassemble(program, pc, natives).ok_or(pc)?;
+        let size = instruction.size;
+        instructions.push((pc, instruction));
+        pc += size;
+    }
+
+    let mut strings = HashMap::new();
+    let mut offset = 0;
+    for string in ro_data.split(|&byte| byte == 0) {
+        if offset < ro_data.len() {
+            strings.insert(offset, (format!("string_{}", offset), string));
+        }
+        offset += string.len() + 1;
+    }
+
+    let starts: HashMap<usize, ()> = instructions.iter().map(|(pc, _)| (*pc, ())).collect();
+    let mut labels = HashMap::new();
+    for (pc, instruction) in instructions.iter_mut() {
+        let target = match (instruction.opcode, instruction.operands.first()) {
+            (Opcode::JMPI, Some(DecodedOperand::Integer(target)))
+            | (Opcode::JEQI, Some(DecodedOperand::Integer(target)))
+            | (Opcode::JNEQI, Some(DecodedOperand::Integer(target))) => *target as usize,
+            (Opcode::JMPR, Some(DecodedOperand::Integer(offset)))
+            | (Opcode::JEQR, Some(DecodedOperand::Integer(offset)))
+            | (Opcode::JNEQR, Some(DecodedOperand::Integer(offset))) => {
+                (*pc + instruction.size).wrapping_add(*offset as isize as usize)
+            }
+            (Opcode::PRTS, Some(DecodedOperand::Integer(offset)))
+            | (Opcode::LDSTR, Some(DecodedOperand::Integer(offset))) => {
+                if let Some((name, _)) = strings.get(&(*offset as usize)) {
+                    instruction.operands[0] = DecodedOperand::Label(name.clone());
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if starts.contains_key(&target) {
+            let name = format!("label_{}", target);
+            instruction.operands[0] = DecodedOperand::Label(name.clone());
+            labels.insert(target, name);
+        }
+    }
+
+    let mut source = String::new();
+    for (pc, instruction) in &instructions {
+        if let Some(name) = labels.get(pc) {
+            source.push_str(&format!("{}: ", name));
+        }
+        source.push_str(&format!("{}\n", instruction));
+    }
+    let mut offsets: Vec<&usize> = strings.keys().collect();
+    offsets.sort_unstable();
+    for offset in offsets {
+        let (name, string) = &strings[offset];
+        source.push_str(&format!("{}: .asciiz '{}'\n", name, escape(string)));
+    }
+    Ok(source)
+}
+
+/// Quotes `string` the way the assembler's string operands are unquoted.
+fn escape(string: &[u8]) -> String {
+    let mut escaped = String::new();
+    for c in String::from_utf8_lossy(string).chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    fn listing(source: &str, natives: &[String]) -> Vec<String> {
+        let program = Assembler::new().assemble(source).unwrap();
+        let mut pc = 0;
+        let mut lines = vec![];
+        while let Some(instruction) = disassemble(&program, pc, natives) {
+            pc += instruction.size;
+            lines.push(instruction.to_string());
+        }
+        assert_eq!(pc, program.len());
+        lines
+    }
+
+    #[test]
+    fn test_disassemble_program() {
+        let source = "load $0 #500\nadd $0 #-3 $1\nloadf $2 #1.5\nsyscall #2\nhlt";
+        assert_eq!(
+            listing(source, &[]),
+            vec!["load $0 #500", "addi $0 #-3 $1", "loadf $2 #1.5", "syscall #2", "hlt"]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_jumps() {
+        let source = "top: load $0 #1\njmp @top\njmpi #0";
+        assert_eq!(listing(source, &[]), vec!["load $0 #1", "jmpr #-7", "jmpi #0"]);
+    }
+
+    #[test]
+    fn test_disassemble_native_call() {
+        let mut vm = crate::vm::VM::new();
+        vm.register_native("max", |_, args| Ok(args[0].max(args[1])));
+        let program = Assembler::for_vm(&vm).assemble("calln @max $1 $2").unwrap();
+        let natives = vm.native_names();
+        let instruction = disassemble(&program, 0, &natives).unwrap();
+        assert_eq!(instruction.to_string(), "calln @max $1 $2");
+        assert_eq!(instruction.size, program.len());
+        assert_eq!(disassemble(&program, 0, &[]).unwrap().to_string(), "calln #0 $1 $2");
+    }
+
+    #[test]
+    fn test_disassemble_truncated() {
+        assert_eq!(disassemble(&[1, 0, 1], 0, &[]), None);
+        assert_eq!(disassemble(&[0], 1, &[]), None);
+    }
+
+    #[test]
+    fn test_disassemble_program_round_trips() {
+        let source = "load $0 #3\nloop: subi $0 #1 $0\nprts @greeting\neq $0 #0\njneq @loop\n\
+            jmpi @end\nldstr @quote $1\nend: hlt\n\
+            greeting: .asciiz 'hi\\n'\nquote: .asciiz 'it\\'s'";
+        let mut assembler = Assembler::new();
+        let program = assembler.assemble(source).unwrap();
+        let ro_data = assembler.ro_data().to_vec();
+
+        let listing = disassemble_program(&program, &ro_data, &[]).unwrap();
+        assert_eq!(
+            listing,
+            "load $0 #3\nlabel_4: subi $0 #1 $0\nprts @string_0\neqi $0 #0\njneqr @label_4\n\
+             jmpi @label_26\nldstr @string_4 $1\nlabel_26: hlt\n\
+             string_0: .asciiz 'hi\\n'\nstring_4: .asciiz 'it\\'s'\n"
+        );
+
+        // Assembled after other code, the labels move with it
+        let mut assembler = Assembler::with_origin(4, 2);
+        let moved = assembler.assemble(&listing).unwrap();
+        assert_eq!(assembler.ro_data(), &ro_data[..]);
+        assert_eq!(moved.len(), program.len());
+        assert_eq!(disassemble(&moved, 19, &[]).unwrap().to_string(), "jmpi #30");
+    }
+
+    #[test]
+    fn test_disassemble_program_reports_truncation() {
+        assert_eq!(disassemble_program(&[0, 1, 0], &[], &[]), Err(1));
+    }
+}
@@ -1,53 +1,228 @@
 use nom::types::CompleteStr;
 
+use super::label_parsers::{label_declaration, label_usage};
 use super::opcode::Token;
 use super::opcode_parser::opcode;
 use super::register_parser::register;
-use super::operand_parser::integer_operand;
+use super::operand_parser::{float_operand, integer_operand};
+use super::symbols::SymbolTable;
+use super::AssemblerError;
+use crate::instruction::{Opcode, Operand};
+
+/// Swaps an opcode for its immediate variant when its last source operand was written as a
+/// `#` literal, so `add $0 #5 $1` assembles to ADDI.
+fn with_immediate(token: Token) -> Token {
+    match token {
+        Token::Op { code } => Token::Op {
+            code: code.immediate_form().unwrap_or(code),
+        },
+        other => other,
+    }
+}
+
+/// Swaps a comparison for the variant that stores its result in a register when a
+/// destination register was given, so `eq $0 $1 $2` assembles to SEQ.
+fn with_register_result(token: Token) -> Token {
+    match token {
+        Token::Op { code } => Token::Op {
+            code: code.register_form().unwrap_or(code),
+        },
+        other => other,
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct AssemblerInstruction {
+    label: Option<Token>,
     opcode: Token,
     operand1: Option<Token>,
     operand2: Option<Token>,
     operand3: Option<Token>,
+    /// Registers after the third operand, which only the argument list of CALLN has.
+    extra_operands: Vec<Token>,
 }
 
 impl AssemblerInstruction {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut results = vec![];
-        match &self.opcode {
-            Token::Op { code } => results.push(*code as u8),
-            _ => panic!("Opcode must be an Op variant"),
+    /// Encodes an instruction that doesn't reference any label.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AssemblerError> {
+        self.encode(&SymbolTable::new(), 0)
+    }
+
+    /// Encodes the instruction as if it was placed at `address`, resolving label operands
+    /// against `symbols`.
+    ///
+    /// A jump to a label (`jmp @loop`) is encoded as a pc-relative jump when the offset fits
+    /// in 16 signed bits, and as an absolute jump otherwise.
+    pub fn encode(&self, symbols: &SymbolTable, address: usize) -> Result<Vec<u8>, AssemblerError> {
+        let mut code = match &self.opcode {
+            Token::Op { code } => *code,
+            _ => return Err(AssemblerError::OpcodeExpected),
+        };
+
+        if let (Some((relative, absolute)), Some(Token::LabelUsage { name })) =
+            (code.branch_forms(), &self.operand1)
+        {
+            let target = AssemblerInstruction::label_address(name, symbols)?;
+            let offset = target as isize - (address + self.size()) as isize;
+            code = if i16::try_from(offset).is_ok() {
+                relative
+            } else {
+                absolute
+            };
+        }
+
+        if code == Opcode::CALLN {
+            return self.encode_native_call(symbols);
+        }
+
+        let mut results = vec![code as u8];
+        let operands = [&self.operand1, &self.operand2, &self.operand3];
+        for (i, operand) in operands.iter().copied().flatten().chain(&self.extra_operands).enumerate() {
+            match operand {
+                Token::LabelUsage { name } => {
+                    let target = AssemblerInstruction::label_address(name, symbols)?;
+                    AssemblerInstruction::check_label_kind(name, code.operands().get(i), symbols)?;
+                    let value = if is_relative_branch(code) {
+                        let offset = target as isize - (address + self.size()) as isize;
+                        i16::try_from(offset)
+                            .map_err(|_| AssemblerError::LabelOutOfRange { name: name.clone() })?
+                            as u16
+                    } else {
+                        u16::try_from(target)
+                            .map_err(|_| AssemblerError::LabelOutOfRange { name: name.clone() })?
+                    };
+                    results.push((value >> 8) as u8);
+                    results.push(value as u8);
+                }
+                // LOADF always takes a float, so `loadf $0 #3` is widened to 3.0
+                Token::IntegerOperand { value } if code == Opcode::LOADF => {
+                    results.extend((*value as f64).to_be_bytes());
+                }
+                _ => AssemblerInstruction::extract_operand(operand, code.operands().get(i), &mut results)?,
+            }
         }
 
-        for operand in [&self.operand1, &self.operand2, &self.operand3].iter().copied().flatten() {
-            AssemblerInstruction::extract_operand(operand, &mut results)
+        let expected = 1 + code.operands().iter().map(|operand| operand.size()).sum::<usize>();
+        if results.len() != expected {
+            return Err(AssemblerError::WrongOperands { opcode: code });
         }
+        Ok(results)
+    }
 
-        results
+    /// `calln @name $a $b ...` is encoded as the native's index, the number of arguments and the
+    /// argument registers.
+    fn encode_native_call(&self, symbols: &SymbolTable) -> Result<Vec<u8>, AssemblerError> {
+        let index = match &self.operand1 {
+            Some(Token::LabelUsage { name }) => symbols
+                .native_index(name)
+                .ok_or_else(|| AssemblerError::UnknownNative { name: name.clone() })?,
+            _ => return Err(AssemblerError::UnexpectedOperand),
+        };
+        let mut results = vec![Opcode::CALLN as u8, (index >> 8) as u8, index as u8, 0];
+        let arguments = [&self.operand2, &self.operand3];
+        for operand in arguments.iter().copied().flatten().chain(&self.extra_operands) {
+            match operand {
+                Token::Register { reg_num } => results.push(register_number(*reg_num)?),
+                _ => return Err(AssemblerError::UnexpectedOperand),
+            }
+        }
+        // The argument count is a single byte
+        results[3] = u8::try_from(results.len() - 4)
+            .map_err(|_| AssemblerError::WrongOperands { opcode: Opcode::CALLN })?;
+        Ok(results)
+    }
+
+    /// Number of bytes the instruction takes once encoded.
+    pub fn size(&self) -> usize {
+        let is_loadf = self.opcode == Token::Op { code: Opcode::LOADF };
+        // CALLN also encodes its argument count
+        let is_calln = self.opcode == Token::Op { code: Opcode::CALLN };
+        let operands = [&self.operand1, &self.operand2, &self.operand3];
+        1 + is_calln as usize
+            + operands
+            .iter()
+            .copied()
+            .flatten()
+            .chain(&self.extra_operands)
+            .map(|operand| match operand {
+                Token::Register { .. } => 1,
+                Token::IntegerOperand { .. } if is_loadf => 8,
+                Token::IntegerOperand { .. } | Token::LabelUsage { .. } => 2,
+                Token::FloatOperand { .. } => 8,
+                _ => 0,
+            })
+            .sum::<usize>()
+    }
+
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.label {
+            Some(Token::LabelDeclaration { name }) => Some(name),
+            _ => None,
+        }
     }
 
-    fn extract_operand(t: &Token, results: &mut Vec<u8>) {
+    fn label_address(name: &str, symbols: &SymbolTable) -> Result<usize, AssemblerError> {
+        symbols
+            .symbol_value(name)
+            .ok_or_else(|| AssemblerError::UnknownLabel { name: name.to_string() })
+    }
+
+    /// Jumps can only target instructions and read-only offsets can only name data.
+    fn check_label_kind(name: &str, kind: Option<&Operand>, symbols: &SymbolTable) -> Result<(), AssemblerError> {
+        match kind {
+            Some(Operand::Address) | Some(Operand::Offset) if symbols.is_data_symbol(name) => {
+                Err(AssemblerError::DataLabelAsCode { name: name.to_string() })
+            }
+            Some(Operand::RoOffset) if !symbols.is_data_symbol(name) => {
+                Err(AssemblerError::CodeLabelAsData { name: name.to_string() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends the bytes of an operand, checking an integer fits in the 16 bits of `kind`:
+    /// signed for immediates the VM sign-extends and offsets, unsigned otherwise.
+    fn extract_operand(t: &Token, kind: Option<&Operand>, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
         match t {
             Token::Register { reg_num } => {
-                results.push(*reg_num);
+                results.push(register_number(*reg_num)?);
             }
             Token::IntegerOperand { value } => {
+                let (min, max) = match kind {
+                    Some(Operand::SignedImmediate) | Some(Operand::Offset) => (i16::MIN as i32, i16::MAX as i32),
+                    _ => (0, u16::MAX as i32),
+                };
+                if *value < min || *value > max {
+                    return Err(AssemblerError::ValueOutOfRange { value: *value, min, max });
+                }
                 let converted = *value as u16;
                 let byte1 = converted;
                 let byte2 = converted >> 8;
                 results.push(byte2 as u8);
                 results.push(byte1 as u8);
             }
-            _ => {
-                println!("Opcode found in operand field");
-                std::process::exit(1);
+            Token::FloatOperand { value } => {
+                results.extend(value.to_be_bytes());
             }
+            _ => return Err(AssemblerError::UnexpectedOperand),
         };
+        Ok(())
     }
 }
 
+/// The VM has 32 integer and 32 float registers.
+fn register_number(reg_num: u8) -> Result<u8, AssemblerError> {
+    if reg_num < 32 {
+        Ok(reg_num)
+    } else {
+        Err(AssemblerError::InvalidRegister { reg_num })
+    }
+}
+
+fn is_relative_branch(code: Opcode) -> bool {
+    matches!(code, Opcode::JMPR | Opcode::JEQR | Opcode::JNEQR)
+}
+
 named!(instruction_one<CompleteStr, AssemblerInstruction>,
     do_parse!(
         o: opcode >>
@@ -55,10 +230,12 @@ named!(instruction_one<CompleteStr, AssemblerInstruction>,
         i: integer_operand >>
         (
             AssemblerInstruction{
-                opcode: o,
+                label: None,
+                opcode: with_immediate(o),
                 operand1: Some(r),
                 operand2: Some(i),
-                operand3: None
+                operand3: None,
+                extra_operands: vec![]
             }
         )
     )
@@ -69,10 +246,12 @@ named!(instruction_two<CompleteStr, AssemblerInstruction>,
         o: opcode >>
         (
             AssemblerInstruction{
+                label: None,
                 opcode: o,
                 operand1: None,
                 operand2: None,
-                operand3: None
+                operand3: None,
+                extra_operands: vec![]
             }
         )
     )
@@ -86,10 +265,12 @@ named!(instruction_three<CompleteStr, AssemblerInstruction>,
         r3: register >>
         (
             AssemblerInstruction{
-                opcode: o,
+                label: None,
+                opcode: with_register_result(o),
                 operand1: Some(r1),
                 operand2: Some(r2),
-                operand3: Some(r3)
+                operand3: Some(r3),
+                extra_operands: vec![]
             }
         )
     )
@@ -102,10 +283,12 @@ named!(instruction_four<CompleteStr, AssemblerInstruction>,
         r2: register >>
         (
             AssemblerInstruction{
+                label: None,
                 opcode: o,
                 operand1: Some(r1),
                 operand2: Some(r2),
-                operand3: None
+                operand3: None,
+                extra_operands: vec![]
             }
         )
     )
@@ -117,20 +300,116 @@ named!(instruction_five<CompleteStr, AssemblerInstruction>,
         r1: register >>
         (
             AssemblerInstruction{
+                label: None,
                 opcode: o,
                 operand1: Some(r1),
                 operand2: None,
-                operand3: None
+                operand3: None,
+                extra_operands: vec![]
             }
         )
     )
 );
 
-named!(pub instruction<CompleteStr, AssemblerInstruction>,
+named!(instruction_six<CompleteStr, AssemblerInstruction>,
+    do_parse!(
+        o: opcode >>
+        r1: register >>
+        i: integer_operand >>
+        r2: register >>
+        (
+            AssemblerInstruction{
+                label: None,
+                opcode: with_immediate(o),
+                operand1: Some(r1),
+                operand2: Some(i),
+                operand3: Some(r2),
+                extra_operands: vec![]
+            }
+        )
+    )
+);
+
+named!(instruction_seven<CompleteStr, AssemblerInstruction>,
     do_parse!(
-        ins: alt!(instruction_one | instruction_three | instruction_four | instruction_five | instruction_two) >>
+        o: opcode >>
+        l: label_usage >>
         (
-            ins
+            AssemblerInstruction{
+                label: None,
+                opcode: o,
+                operand1: Some(l),
+                operand2: None,
+                operand3: None,
+                extra_operands: vec![]
+            }
+        )
+    )
+);
+
+named!(instruction_eight<CompleteStr, AssemblerInstruction>,
+    do_parse!(
+        o: opcode >>
+        r: register >>
+        f: float_operand >>
+        (
+            AssemblerInstruction{
+                label: None,
+                opcode: o,
+                operand1: Some(r),
+                operand2: Some(f),
+                operand3: None,
+                extra_operands: vec![]
+            }
+        )
+    )
+);
+
+named!(instruction_nine<CompleteStr, AssemblerInstruction>,
+    do_parse!(
+        o: opcode >>
+        l: label_usage >>
+        r: register >>
+        rest: many0!(register) >>
+        ({
+            let mut rest = rest.into_iter();
+            AssemblerInstruction{
+                label: None,
+                opcode: o,
+                operand1: Some(l),
+                operand2: Some(r),
+                operand3: rest.next(),
+                extra_operands: rest.collect()
+            }
+        })
+    )
+);
+
+named!(instruction_ten<CompleteStr, AssemblerInstruction>,
+    do_parse!(
+        o: opcode >>
+        i: integer_operand >>
+        (
+            AssemblerInstruction{
+                label: None,
+                opcode: o,
+                operand1: Some(i),
+                operand2: None,
+                operand3: None,
+                extra_operands: vec![]
+            }
+        )
+    )
+);
+
+named!(pub instruction<CompleteStr, AssemblerInstruction>,
+    ws!(
+        do_parse!(
+            l: opt!(label_declaration) >>
+            ins: alt!(instruction_six | instruction_eight | instruction_one | instruction_three | instruction_four | instruction_nine | instruction_seven | instruction_ten | instruction_five | instruction_two) >>
+            (
+                AssemblerInstruction { label: l, ..ins }
+            )
         )
     )
 );
@@ -138,7 +417,6 @@ named!(pub instruction<CompleteStr, AssemblerInstruction>,
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::instruction::Opcode;
 
     #[test]
     fn test_parse_instruction_form_one() {
@@ -148,10 +426,12 @@ mod tests {
             Ok((
                 CompleteStr(""),
                 AssemblerInstruction {
+                    label: None,
                     opcode: Token::Op { code: Opcode::LOAD },
                     operand1: Some(Token::Register { reg_num: 0 }),
                     operand2: Some(Token::IntegerOperand { value: 100 }),
-                    operand3: None
+                    operand3: None,
+                    extra_operands: vec![]
                 }
             ))
         );
@@ -165,12 +445,111 @@ mod tests {
             Ok((
                 CompleteStr("\n"),
                 AssemblerInstruction {
+                    label: None,
                     opcode: Token::Op { code: Opcode::HLT },
                     operand1: None,
                     operand2: None,
-                    operand3: None
+                    operand3: None,
+                    extra_operands: vec![]
                 }
             ))
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_instruction_form_six() {
+        let result = instruction(CompleteStr("add $0 #-2 $1\n"));
+        assert_eq!(
+            result,
+            Ok((
+                CompleteStr(""),
+                AssemblerInstruction {
+                    label: None,
+                    opcode: Token::Op { code: Opcode::ADDI },
+                    operand1: Some(Token::Register { reg_num: 0 }),
+                    operand2: Some(Token::IntegerOperand { value: -2 }),
+                    operand3: Some(Token::Register { reg_num: 1 }),
+                    extra_operands: vec![]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_immediate_comparison() {
+        let (_, ins) = instruction(CompleteStr("gte $3 #100\n")).unwrap();
+        assert_eq!(ins.opcode, Token::Op { code: Opcode::GTQI });
+        assert_eq!(ins.to_bytes(), Ok(vec![35, 3, 0, 100]));
+        // LOAD has no immediate form and must be left alone
+        let (_, ins) = instruction(CompleteStr("load $0 #100\n")).unwrap();
+        assert_eq!(ins.opcode, Token::Op { code: Opcode::LOAD });
+    }
+
+    #[test]
+    fn test_parse_register_comparison() {
+        let (_, ins) = instruction(CompleteStr("eq $0 $1 $2\n")).unwrap();
+        assert_eq!(ins.opcode, Token::Op { code: Opcode::SEQ });
+        assert_eq!(ins.to_bytes(), Ok(vec![37, 0, 1, 2]));
+        // Without a destination the flag-setting form is kept
+        let (_, ins) = instruction(CompleteStr("eq $0 $1\n")).unwrap();
+        assert_eq!(ins.opcode, Token::Op { code: Opcode::EQ });
+        // Arithmetic is not affected
+        let (_, ins) = instruction(CompleteStr("add $0 $1 $2\n")).unwrap();
+        assert_eq!(ins.opcode, Token::Op { code: Opcode::ADD });
+    }
+
+    #[test]
+    fn test_parse_labelled_instruction() {
+        let (rest, ins) = instruction(CompleteStr("loop: jmp @loop\nhlt")).unwrap();
+        assert_eq!(rest, CompleteStr("hlt"));
+        assert_eq!(ins.label_name(), Some("loop"));
+        assert_eq!(ins.operand1, Some(Token::LabelUsage { name: "loop".to_string() }));
+        assert_eq!(ins.size(), 3);
+    }
+
+    #[test]
+    fn test_encode_branch_to_label() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_symbol("loop", 4);
+        symbols.add_symbol("far", 40000);
+        let (_, ins) = instruction(CompleteStr("jeq @loop")).unwrap();
+        assert_eq!(ins.encode(&symbols, 10), Ok(vec![47, 0xFF, 0xF7]));
+        let (_, ins) = instruction(CompleteStr("jmp @far")).unwrap();
+        assert_eq!(ins.encode(&symbols, 0), Ok(vec![43, 0x9C, 0x40]));
+        let (_, ins) = instruction(CompleteStr("jmpi @loop")).unwrap();
+        assert_eq!(ins.encode(&symbols, 10), Ok(vec![43, 0, 4]));
+        let (_, ins) = instruction(CompleteStr("jmpr @far")).unwrap();
+        assert_eq!(
+            ins.encode(&symbols, 0),
+            Err(AssemblerError::LabelOutOfRange { name: "far".to_string() })
+        );
+        let (_, ins) = instruction(CompleteStr("jmp @nowhere")).unwrap();
+        assert_eq!(
+            ins.encode(&symbols, 0),
+            Err(AssemblerError::UnknownLabel { name: "nowhere".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_parse_float_instruction() {
+        let (rest, ins) = instruction(CompleteStr("loadf $2 #-1.25\nhlt")).unwrap();
+        assert_eq!(rest, CompleteStr("hlt"));
+        assert_eq!(ins.operand2, Some(Token::FloatOperand { value: -1.25 }));
+        let mut expected = vec![49, 2];
+        expected.extend((-1.25f64).to_be_bytes());
+        assert_eq!(ins.size(), 10);
+        assert_eq!(ins.to_bytes(), Ok(expected));
+
+        let (_, ins) = instruction(CompleteStr("loadf $2 #4")).unwrap();
+        let mut expected = vec![49, 2];
+        expected.extend(4.0f64.to_be_bytes());
+        assert_eq!(ins.size(), 10);
+        assert_eq!(ins.to_bytes(), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_syscall() {
+        let (_, ins) = instruction(CompleteStr("syscall #3")).unwrap();
+        assert_eq!(ins.to_bytes(), Ok(vec![68, 0, 3]));
+    }
+}
@@ -1,6 +1,328 @@
+use std::fmt;
+
+use nom::types::CompleteStr;
+
+use self::program_parser::{program, Program};
+use self::symbols::SymbolTable;
+use crate::instruction::Opcode;
+use crate::vm::VM;
+
+pub mod disassembler;
 pub mod opcode;
 pub mod opcode_parser;
 pub mod register_parser;
 pub mod operand_parser;
+pub mod label_parsers;
+pub mod directive_parsers;
 pub mod instruction_parser;
-pub mod program_parser;
\ No newline at end of file
+pub mod program_parser;
+pub mod symbols;
+
+#[derive(Debug, PartialEq)]
+pub enum AssemblerError {
+    ParseError { input: String },
+    OpcodeExpected,
+    UnexpectedOperand,
+    DuplicateLabel { name: String },
+    UnknownLabel { name: String },
+    LabelOutOfRange { name: String },
+    /// A label on read-only data used where an instruction address is expected.
+    DataLabelAsCode { name: String },
+    /// A label on an instruction used where a read-only offset is expected.
+    CodeLabelAsData { name: String },
+    UnknownDirective { name: String },
+    InvalidDirectiveOperand { name: String },
+    UnknownNative { name: String },
+    /// A register past `$31`.
+    InvalidRegister { reg_num: u8 },
+    /// An integer operand doesn't fit in the 16 bits the opcode reads it from.
+    ValueOutOfRange { value: i32, min: i32, max: i32 },
+    /// The operands don't encode to the ones the opcode reads.
+    WrongOperands { opcode: Opcode },
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblerError::ParseError { input } => write!(f, "Unable to parse `{}`", input),
+            AssemblerError::OpcodeExpected => write!(f, "Expected an opcode"),
+            AssemblerError::UnexpectedOperand => write!(f, "Opcode found in operand field"),
+            AssemblerError::DuplicateLabel { name } => write!(f, "Label `{}` is declared twice", name),
+            AssemblerError::UnknownLabel { name } => write!(f, "Label `{}` is never declared", name),
+            AssemblerError::LabelOutOfRange { name } => {
+                write!(f, "Label `{}` is too far away to be encoded", name)
+            }
+            AssemblerError::DataLabelAsCode { name } => {
+                write!(f, "Label `{}` names read-only data, not an instruction", name)
+            }
+            AssemblerError::CodeLabelAsData { name } => {
+                write!(f, "Label `{}` names an instruction, not read-only data", name)
+            }
+            AssemblerError::UnknownDirective { name } => write!(f, "Unknown directive `.{}`", name),
+            AssemblerError::InvalidDirectiveOperand { name } => {
+                write!(f, "Directive `.{}` is missing its operand", name)
+            }
+            AssemblerError::UnknownNative { name } => {
+                write!(f, "Native function `{}` is not registered", name)
+            }
+            AssemblerError::InvalidRegister { reg_num } => {
+                write!(f, "Register `${}` doesn't exist, expected $0 to $31", reg_num)
+            }
+            AssemblerError::ValueOutOfRange { value, min, max } => {
+                write!(f, "`#{}` is out of range, expected a value from {} to {}", value, min, max)
+            }
+            AssemblerError::WrongOperands { opcode } => {
+                let operands: Vec<&str> = opcode.operands().iter().map(|operand| operand.syntax()).collect();
+                write!(f, "`{}` expects the operands {}", opcode.mnemonic(), operands.join(" "))
+            }
+        }
+    }
+}
+
+/// Two pass assembler: the first pass records the address of every label, the second one
+/// encodes the instructions with the labels resolved.
+///
+/// Labels on instructions resolve to addresses in the program, labels on directives resolve
+/// to offsets in the read-only section.
+#[derive(Debug, Default)]
+pub struct Assembler {
+    origin: usize,
+    ro_origin: usize,
+    symbols: SymbolTable,
+    ro_data: Vec<u8>,
+    natives: Vec<String>,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::with_origin(0, 0)
+    }
+
+    /// Creates an assembler for code that will be loaded at `origin` in the program, and data
+    /// that will be loaded at `ro_origin` in the read-only section, which is what labels are
+    /// computed from.
+    pub fn with_origin(origin: usize, ro_origin: usize) -> Assembler {
+        Assembler {
+            origin,
+            ro_origin,
+            symbols: SymbolTable::new(),
+            ro_data: vec![],
+            natives: vec![],
+        }
+    }
+
+    /// Creates an assembler for code appended to `vm`'s program, with `calln` resolved against
+    /// the native functions registered on it.
+    pub fn for_vm(vm: &VM) -> Assembler {
+        Assembler {
+            natives: vm.native_names(),
+            ..Assembler::with_origin(vm.program.len(), vm.ro_data.len())
+        }
+    }
+
+    /// Assembles `raw` and returns the bytecode. The read-only data it declares is available
+    /// through `ro_data` afterwards.
+    pub fn assemble(&mut self, raw: &str) -> Result<Vec<u8>, AssemblerError> {
+        let program = match program(CompleteStr(raw)) {
+            Ok((rest, program)) if rest.trim().is_empty() => program,
+            Ok((rest, _)) => return Err(Assembler::parse_error(&rest)),
+            Err(_) => return Err(Assembler::parse_error(raw)),
+        };
+        self.symbols = self.extract_labels(&program)?;
+        self.ro_data = program.ro_data()?;
+        program.encode(&self.symbols, self.origin)
+    }
+
+    /// Symbols found by the last call to `assemble`.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Read-only data declared by the last call to `assemble`.
+    pub fn ro_data(&self) -> &[u8] {
+        &self.ro_data
+    }
+
+    fn extract_labels(&self, program: &Program) -> Result<SymbolTable, AssemblerError> {
+        let mut symbols = SymbolTable::new();
+        for (index, name) in self.natives.iter().enumerate() {
+            symbols.add_native(name, index as u16);
+        }
+        let mut address = self.origin;
+        for instruction in &program.instructions {
+            if let Some(name) = instruction.label_name() {
+                if !symbols.add_symbol(name, address) {
+                    return Err(AssemblerError::DuplicateLabel { name: name.to_string() });
+                }
+            }
+            address += instruction.size();
+        }
+        let mut offset = self.ro_origin;
+        for directive in &program.directives {
+            if let Some(name) = directive.label_name() {
+                if !symbols.add_data_symbol(name, offset) {
+                    return Err(AssemblerError::DuplicateLabel { name: name.to_string() });
+                }
+            }
+            offset += directive.to_bytes()?.len();
+        }
+        Ok(symbols)
+    }
+
+    fn parse_error(input: &str) -> AssemblerError {
+        AssemblerError::ParseError {
+            input: input.trim().lines().next().unwrap_or("").to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_backward_loop() {
+        let mut assembler = Assembler::new();
+        let bytes = assembler.assemble("load $0 #3\nloop: subi $0 #1 $0\neq $0 #0\njneq @loop\nhlt\n");
+        assert_eq!(
+            bytes,
+            Ok(vec![1, 0, 0, 3, 29, 0, 0, 1, 0, 31, 0, 0, 0, 48, 0xFF, 0xF4, 0])
+        );
+        assert_eq!(assembler.symbols().symbol_value("loop"), Some(4));
+    }
+
+    #[test]
+    fn test_assemble_forward_label_with_origin() {
+        let mut assembler = Assembler::with_origin(100, 0);
+        let bytes = assembler.assemble("jmpi @end\nhlt\nend: hlt");
+        assert_eq!(bytes, Ok(vec![43, 0, 104, 0, 0]));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let mut assembler = Assembler::new();
+        assert_eq!(
+            assembler.assemble("a: hlt\na: hlt"),
+            Err(AssemblerError::DuplicateLabel { name: "a".to_string() })
+        );
+        assert_eq!(
+            assembler.assemble("hlt\n%1"),
+            Err(AssemblerError::ParseError { input: "%1".to_string() })
+        );
+        let missing = assembler.assemble("load $0");
+        assert_eq!(missing, Err(AssemblerError::WrongOperands { opcode: Opcode::LOAD }));
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "`load` expects the operands $<register> #<value>"
+        );
+        assert_eq!(
+            assembler.assemble("add $0 $1"),
+            Err(AssemblerError::WrongOperands { opcode: Opcode::ADD })
+        );
+    }
+
+    #[test]
+    fn test_assemble_invalid_registers() {
+        let mut vm = VM::new();
+        vm.register_native("first", |_, _| Ok(0));
+        let mut assembler = Assembler::for_vm(&vm);
+        let load = assembler.assemble("load $40 #1");
+        assert_eq!(load, Err(AssemblerError::InvalidRegister { reg_num: 40 }));
+        assert_eq!(load.unwrap_err().to_string(), "Register `$40` doesn't exist, expected $0 to $31");
+        assert_eq!(
+            assembler.assemble("addf $0 $1 $32"),
+            Err(AssemblerError::InvalidRegister { reg_num: 32 })
+        );
+        assert_eq!(
+            assembler.assemble("calln @first $1 $99"),
+            Err(AssemblerError::InvalidRegister { reg_num: 99 })
+        );
+        assert_eq!(
+            assembler.assemble("load $300 #1"),
+            Err(AssemblerError::ParseError { input: "$300 #1".to_string() })
+        );
+        assert!(assembler.assemble("add $0 $31 $31").is_ok());
+    }
+
+    #[test]
+    fn test_assemble_out_of_range_values() {
+        let mut assembler = Assembler::new();
+        let negative = assembler.assemble("load $0 #-5");
+        assert_eq!(negative, Err(AssemblerError::ValueOutOfRange { value: -5, min: 0, max: 65535 }));
+        assert_eq!(
+            negative.unwrap_err().to_string(),
+            "`#-5` is out of range, expected a value from 0 to 65535"
+        );
+        assert_eq!(
+            assembler.assemble("add $1 #40000 $2"),
+            Err(AssemblerError::ValueOutOfRange { value: 40000, min: -32768, max: 32767 })
+        );
+        assert_eq!(
+            assembler.assemble("jmpr #-40000"),
+            Err(AssemblerError::ValueOutOfRange { value: -40000, min: -32768, max: 32767 })
+        );
+        assert_eq!(
+            assembler.assemble("load $0 #99999999999"),
+            Err(AssemblerError::ParseError { input: "#99999999999".to_string() })
+        );
+        assert!(assembler.assemble("load $0 #65535\naddi $0 #-32768 $1\nloadf $0 #-5").is_ok());
+    }
+
+    #[test]
+    fn test_assemble_strings() {
+        let mut assembler = Assembler::with_origin(0, 3);
+        let bytes = assembler.assemble("hello: .asciiz 'Hi'\nprts @hello\nbye: .asciiz 'Bye'\nprts @bye");
+        assert_eq!(bytes, Ok(vec![62, 0, 3, 62, 0, 6]));
+        assert_eq!(assembler.ro_data(), b"Hi\0Bye\0");
+    }
+
+    #[test]
+    fn test_assemble_label_kinds() {
+        let mut assembler = Assembler::new();
+        let jump = assembler.assemble("msg: .asciiz 'Hi'\njmp @msg");
+        assert_eq!(jump, Err(AssemblerError::DataLabelAsCode { name: "msg".to_string() }));
+        assert_eq!(jump.unwrap_err().to_string(), "Label `msg` names read-only data, not an instruction");
+        assert_eq!(
+            assembler.assemble("msg: .asciiz 'Hi'\njeqr @msg"),
+            Err(AssemblerError::DataLabelAsCode { name: "msg".to_string() })
+        );
+        assert_eq!(
+            assembler.assemble("loop: prts @loop"),
+            Err(AssemblerError::CodeLabelAsData { name: "loop".to_string() })
+        );
+        assert_eq!(
+            assembler.assemble("start: hlt\nldstr @start $0"),
+            Err(AssemblerError::CodeLabelAsData { name: "start".to_string() })
+        );
+        assert!(assembler.assemble("msg: .asciiz 'Hi'\nstart: prts @msg\njmp @start").is_ok());
+    }
+
+    #[test]
+    fn test_assemble_native_calls() {
+        let mut vm = VM::new();
+        vm.register_native("first", |_, _| Ok(0));
+        vm.register_native("second", |_, _| Ok(0));
+        let mut assembler = Assembler::for_vm(&vm);
+        assert_eq!(
+            assembler.assemble("calln @second $1 $2\ncalln @first"),
+            Ok(vec![69, 0, 1, 2, 1, 2, 69, 0, 0, 0])
+        );
+        assert_eq!(
+            assembler.assemble("calln @first $1 $2 $3 $4"),
+            Ok(vec![69, 0, 0, 4, 1, 2, 3, 4])
+        );
+        let too_many = vec!["$1"; 256].join(" ");
+        assert_eq!(
+            assembler.assemble(&format!("calln @first {}", too_many)),
+            Err(AssemblerError::WrongOperands { opcode: Opcode::CALLN })
+        );
+        assert_eq!(
+            assembler.assemble("msg: .asciiz 'Hi'\nldstr @msg $1 $2"),
+            Err(AssemblerError::WrongOperands { opcode: Opcode::LDSTR })
+        );
+        assert_eq!(
+            assembler.assemble("calln @third"),
+            Err(AssemblerError::UnknownNative { name: "third".to_string() })
+        );
+    }
+}
@@ -6,4 +6,9 @@ pub enum Token {
     Op{code: Opcode},
     Register{reg_num: u8},
     IntegerOperand{value: i32},
-}
\ No newline at end of file
+    FloatOperand{value: f64},
+    LabelDeclaration{name: String},
+    LabelUsage{name: String},
+    Directive{name: String},
+    StringOperand{value: String},
+}
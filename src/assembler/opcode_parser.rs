@@ -20,6 +20,7 @@ mod tests {
     use nom::types::CompleteStr;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_opcode() {
         let result = opcode(CompleteStr("load"));
         assert_eq!(result.is_ok(), true);
@@ -1,28 +1,78 @@
-use nom::digit;
 use nom::types::CompleteStr;
+use nom::{digit, Context, Err, ErrorKind, IResult};
 
 use super::opcode::Token;
 
 // Parser for integer numbers, which we preface with `#` in our assembly language:
-// #100
+// #100, #-5
 named!(
     pub integer_operand<CompleteStr, Token>,
     ws!(
         do_parse!(
             tag!("#") >>
-            reg_num: digit >>
+            value: map_res!(recognize!(pair!(opt!(tag!("-")), digit)), |value: CompleteStr| value.parse::<i32>()) >>
             (
-                Token::IntegerOperand{value: reg_num.parse::<i32>().unwrap()}
+                Token::IntegerOperand{value}
             )
         )
     )
 );
 
+// Parser for float numbers, which also use `#` but must contain a decimal point:
+// #3.14, #-0.5
+named!(
+    pub float_operand<CompleteStr, Token>,
+    ws!(
+        do_parse!(
+            tag!("#") >>
+            value: recognize!(tuple!(opt!(tag!("-")), digit, tag!("."), digit)) >>
+            (
+                Token::FloatOperand{value: value.parse::<f64>().unwrap()}
+            )
+        )
+    )
+);
+
+// Parser for string literals, delimited by single or double quotes. `\n`, `\t`, `\0`, `\\`
+// and escaped quotes are understood:
+// 'Hello, World!\n'
+named!(
+    pub string_operand<CompleteStr, Token>,
+    ws!(call!(quoted_string))
+);
+
+fn quoted_string(input: CompleteStr) -> IResult<CompleteStr, Token> {
+    let error = Err::Error(Context::Code(input, ErrorKind::Custom(0)));
+    let mut chars = input.char_indices();
+    let quote = match chars.next() {
+        Some((_, c)) if c == '\'' || c == '"' => c,
+        _ => return Err(error),
+    };
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, '0')) => value.push('\0'),
+                Some((_, escaped)) => value.push(escaped),
+                None => return Err(error),
+            },
+            c if c == quote => {
+                let rest = CompleteStr(&input.0[i + c.len_utf8()..]);
+                return Ok((rest, Token::StringOperand { value }));
+            }
+            c => value.push(c),
+        }
+    }
+    Err(error)
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_parse_integer_operand() {
         // Test a valid integer operand
         let result = integer_operand(CompleteStr("#10"));
@@ -31,8 +81,49 @@ mod tests {
         assert_eq!(rest, CompleteStr(""));
         assert_eq!(value, Token::IntegerOperand { value: 10 });
 
+        // Test a negative one
+        let result = integer_operand(CompleteStr("#-10"));
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Token::IntegerOperand { value: -10 });
+
         // Test an invalid one (missing the #)
         let result = integer_operand(CompleteStr("10"));
         assert_eq!(result.is_ok(), false);
+
+        // Too large to be any operand
+        let result = integer_operand(CompleteStr("#99999999999"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_float_operand() {
+        let result = float_operand(CompleteStr("#2.75"));
+        let (rest, value) = result.unwrap();
+        assert_eq!(rest, CompleteStr(""));
+        assert_eq!(value, Token::FloatOperand { value: 2.75 });
+
+        let result = float_operand(CompleteStr("#-0.5"));
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Token::FloatOperand { value: -0.5 });
+
+        // Integers are not floats
+        let result = float_operand(CompleteStr("#3"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_string_operand() {
+        let result = string_operand(CompleteStr("'Hello, World!\\n' rest"));
+        let (rest, value) = result.unwrap();
+        assert_eq!(rest, CompleteStr("rest"));
+        assert_eq!(value, Token::StringOperand { value: "Hello, World!\n".to_string() });
+
+        let result = string_operand(CompleteStr("\"it's\""));
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Token::StringOperand { value: "it's".to_string() });
+
+        // Unterminated strings are rejected
+        let result = string_operand(CompleteStr("'oops"));
+        assert!(result.is_err());
     }
 }
@@ -1,30 +1,74 @@
 use nom::types::CompleteStr;
 
+use super::directive_parsers::{directive, AssemblerDirective};
 use super::instruction_parser::{instruction, AssemblerInstruction};
+use super::symbols::SymbolTable;
+use super::AssemblerError;
 
 #[derive(Debug, PartialEq)]
 pub struct Program {
     pub instructions: Vec<AssemblerInstruction>,
+    pub directives: Vec<AssemblerDirective>,
+}
+
+enum Line {
+    Instruction(AssemblerInstruction),
+    Directive(AssemblerDirective),
 }
 
 impl Program {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// Encodes a program that doesn't reference any label.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AssemblerError> {
+        let mut bytes = Vec::new();
+        for instruction in &self.instructions {
+            bytes.extend(instruction.to_bytes()?);
+        }
+        Ok(bytes)
+    }
+
+    /// Encodes the program as if it was loaded at `origin`, resolving labels against `symbols`.
+    pub fn encode(&self, symbols: &SymbolTable, origin: usize) -> Result<Vec<u8>, AssemblerError> {
         let mut bytes = Vec::new();
         for instruction in &self.instructions {
-            bytes.extend(instruction.to_bytes());
+            bytes.extend(instruction.encode(symbols, origin + bytes.len())?);
+        }
+        Ok(bytes)
+    }
+
+    /// Encodes the data of the read-only section, in the order it was declared.
+    pub fn ro_data(&self) -> Result<Vec<u8>, AssemblerError> {
+        let mut bytes = Vec::new();
+        for directive in &self.directives {
+            bytes.extend(directive.to_bytes()?);
+        }
+        Ok(bytes)
+    }
+}
+
+fn split_lines(lines: Vec<Line>) -> Program {
+    let mut instructions = vec![];
+    let mut directives = vec![];
+    for line in lines {
+        match line {
+            Line::Instruction(i) => instructions.push(i),
+            Line::Directive(d) => directives.push(d),
         }
-        bytes
+    }
+    Program {
+        instructions,
+        directives,
     }
 }
 
 named!(
     pub program<CompleteStr, Program>,
     do_parse!(
-        instructions: many1!(instruction) >>
+        lines: many1!(alt!(
+            directive => { Line::Directive } |
+            instruction => { Line::Instruction }
+        )) >>
         (
-            Program {
-                instructions
-            }
+            split_lines(lines)
         )
     )
 );
@@ -33,6 +77,7 @@ named!(
 mod tests {
     use super::*;
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_parse_program() {
         let result = program(CompleteStr("load $0 #100\n"));
         assert_eq!(result.is_ok(), true);
@@ -43,12 +88,41 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_program_to_bytes() {
         let result = program(CompleteStr("load $0 #100\n"));
         assert_eq!(result.is_ok(), true);
         let (_, program) = result.unwrap();
-        let bytecode = program.to_bytes();
+        let bytecode = program.to_bytes().unwrap();
         assert_eq!(bytecode.len(), 4);
         println!("{:?}", bytecode);
     }
+
+    #[test]
+    fn test_bitwise_program_to_bytes() {
+        let result = program(CompleteStr("and $0 $1 $2\nnot $2 $3\nsar $3 $4 $5\n"));
+        assert!(result.is_ok());
+        let (leftover, program) = result.unwrap();
+        assert_eq!(leftover, CompleteStr(""));
+        assert_eq!(program.to_bytes(), Ok(vec![18, 0, 1, 2, 21, 2, 3, 24, 3, 4, 5]));
+    }
+
+    #[test]
+    fn test_program_to_bytes_reports_errors() {
+        let (_, program) = program(CompleteStr("hlt\njmp @nowhere\n")).unwrap();
+        assert_eq!(
+            program.to_bytes(),
+            Err(AssemblerError::UnknownLabel { name: "nowhere".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_directives() {
+        let result = program(CompleteStr("a: .asciiz 'x'\nprts @a\nb: .asciiz 'yz'\nhlt\n"));
+        let (leftover, p) = result.unwrap();
+        assert_eq!(leftover, CompleteStr(""));
+        assert_eq!(p.instructions.len(), 2);
+        assert_eq!(p.directives.len(), 2);
+        assert_eq!(p.ro_data(), Ok(vec![b'x', 0, b'y', b'z', 0]));
+    }
 }
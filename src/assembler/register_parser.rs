@@ -7,9 +7,9 @@ named!(
     ws!(
         do_parse!(
             tag!("$") >>
-            reg_num: digit >>
+            reg_num: map_res!(digit, |reg_num: CompleteStr| reg_num.parse::<u8>()) >>
             (
-                Token::Register { reg_num: reg_num.parse().unwrap() }
+                Token::Register { reg_num }
             )
         )
     )
@@ -19,6 +19,7 @@ named!(
 mod tests {
     use super::*;
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_parse_register() {
         let result = register(CompleteStr("$0"));
         assert_eq!(result.is_ok(), true);
@@ -27,4 +28,10 @@ mod tests {
         let result = register(CompleteStr("$a"));
         assert_eq!(result.is_ok(), false);
     }
+
+    #[test]
+    fn test_register_number_too_large() {
+        assert_eq!(register(CompleteStr("$255")).unwrap().1, Token::Register { reg_num: 255 });
+        assert!(register(CompleteStr("$300")).is_err());
+    }
 }
\ No newline at end of file
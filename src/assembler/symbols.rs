@@ -0,0 +1,99 @@
+use std::collections::{HashMap, HashSet};
+
+/// Maps label names to the absolute address of the instruction they were declared on, or
+/// the read-only offset of the data they were declared on, and the names of the native
+/// functions a program may call to their index in the VM's registry.
+#[derive(Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    symbols: HashMap<String, usize>,
+    data_symbols: HashSet<String>,
+    natives: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            symbols: HashMap::new(),
+            data_symbols: HashSet::new(),
+            natives: HashMap::new(),
+        }
+    }
+
+    pub fn add_native(&mut self, name: &str, index: u16) {
+        self.natives.insert(name.to_string(), index);
+    }
+
+    pub fn native_index(&self, name: &str) -> Option<u16> {
+        self.natives.get(name).copied()
+    }
+
+    /// Adds a symbol, returning `false` if a symbol with that name already exists.
+    pub fn add_symbol(&mut self, name: &str, address: usize) -> bool {
+        if self.symbols.contains_key(name) {
+            return false;
+        }
+        self.symbols.insert(name.to_string(), address);
+        true
+    }
+
+    /// Adds a symbol naming an offset in the read-only section rather than an instruction.
+    pub fn add_data_symbol(&mut self, name: &str, offset: usize) -> bool {
+        if !self.add_symbol(name, offset) {
+            return false;
+        }
+        self.data_symbols.insert(name.to_string());
+        true
+    }
+
+    pub fn symbol_value(&self, name: &str) -> Option<usize> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Whether `name` was declared on read-only data rather than on an instruction.
+    pub fn is_data_symbol(&self, name: &str) -> bool {
+        self.data_symbols.contains(name)
+    }
+
+    /// The labels declared on instructions, with their addresses.
+    pub fn code_labels(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.symbols
+            .iter()
+            .filter(move |(name, _)| !self.data_symbols.contains(*name))
+            .map(|(name, address)| (name.as_str(), *address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_table() {
+        let mut symbols = SymbolTable::new();
+        assert!(symbols.add_symbol("loop", 12));
+        assert!(!symbols.add_symbol("loop", 20));
+        assert_eq!(symbols.symbol_value("loop"), Some(12));
+        assert_eq!(symbols.symbol_value("end"), None);
+    }
+
+    #[test]
+    fn test_natives_are_a_separate_namespace() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_native("print", 3);
+        assert!(symbols.add_symbol("print", 12));
+        assert_eq!(symbols.native_index("print"), Some(3));
+        assert_eq!(symbols.symbol_value("print"), Some(12));
+    }
+
+    #[test]
+    fn test_code_labels_leave_out_data() {
+        let mut symbols = SymbolTable::new();
+        assert!(symbols.add_symbol("loop", 12));
+        assert!(symbols.add_data_symbol("greeting", 0));
+        assert!(!symbols.add_data_symbol("loop", 4));
+        assert_eq!(symbols.symbol_value("greeting"), Some(0));
+        assert!(symbols.is_data_symbol("greeting"));
+        assert!(!symbols.is_data_symbol("loop"));
+        assert_eq!(symbols.code_labels().collect::<Vec<_>>(), vec![("loop", 12)]);
+    }
+}
@@ -1,5 +1,73 @@
 use nom::types::CompleteStr;
 
+/// The kinds of operand an instruction can encode after its opcode byte.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+    /// One byte naming an integer register.
+    Register,
+    /// One byte naming a float register.
+    FloatRegister,
+    /// Two bytes, zero-extended (LOAD).
+    Immediate,
+    /// Two bytes, sign-extended.
+    SignedImmediate,
+    /// Two bytes holding an absolute address in the program.
+    Address,
+    /// Two bytes holding a signed offset from the end of the instruction.
+    Offset,
+    /// Two bytes holding an offset in the read-only section.
+    RoOffset,
+    /// Eight bytes holding an f64.
+    Float,
+    /// Two bytes holding a syscall number.
+    Syscall,
+    /// Two bytes holding a native's index, then an argument count and that many registers.
+    Native,
+}
+
+impl Operand {
+    /// Number of bytes the operand takes, not counting CALLN's variable argument list.
+    pub fn size(self) -> usize {
+        match self {
+            Operand::Register | Operand::FloatRegister => 1,
+            Operand::Float => 8,
+            Operand::Native => 3,
+            _ => 2,
+        }
+    }
+
+    /// How the operand is written in assembly.
+    pub fn syntax(self) -> &'static str {
+        match self {
+            Operand::Register => "$<register>",
+            Operand::FloatRegister => "$<float register>",
+            Operand::Immediate | Operand::SignedImmediate => "#<value>",
+            Operand::Address => "#<address>|@<label>",
+            Operand::Offset => "#<offset>|@<label>",
+            Operand::RoOffset => "@<string>",
+            Operand::Float => "#<float>",
+            Operand::Syscall => "#<syscall>",
+            Operand::Native => "@<native> [$<register>...]",
+        }
+    }
+
+    /// What the operand's bytes hold, as shown by `.help <mnemonic>`.
+    pub fn encoding(self) -> &'static str {
+        match self {
+            Operand::Register => "register (1 byte)",
+            Operand::FloatRegister => "float register (1 byte)",
+            Operand::Immediate => "unsigned value (2 bytes)",
+            Operand::SignedImmediate => "signed value (2 bytes)",
+            Operand::Address => "address (2 bytes)",
+            Operand::Offset => "signed offset (2 bytes)",
+            Operand::RoOffset => "read-only offset (2 bytes)",
+            Operand::Float => "f64 (8 bytes)",
+            Operand::Syscall => "syscall number (2 bytes)",
+            Operand::Native => "native index (2 bytes), argument count (1 byte), one register per argument (1 byte each)",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Opcode {
     HLT,
@@ -19,58 +87,664 @@ pub enum Opcode {
     LTQ,
     JEQ,
     JNEQ,
-    IGL,
     ALOC,
+    AND,
+    OR,
+    XOR,
+    NOT,
+    SHL,
+    SHR,
+    SAR,
+    MOVREM,
+    MOD,
+    MOV,
+    ADDI,
+    SUBI,
+    MULI,
+    EQI,
+    NEQI,
+    GTI,
+    LTI,
+    GTQI,
+    LTQI,
+    SEQ,
+    SNEQ,
+    SGT,
+    SLT,
+    SGTQ,
+    SLTQ,
+    JMPI,
+    JEQI,
+    JNEQI,
+    JMPR,
+    JEQR,
+    JNEQR,
+    LOADF,
+    ADDF,
+    SUBF,
+    MULF,
+    DIVF,
+    EQF,
+    NEQF,
+    GTF,
+    LTF,
+    GTQF,
+    LTQF,
+    ITOF,
+    FTOI,
+    PRTS,
+    PRTH,
+    LDSTR,
+    STRCAT,
+    STRLEN,
+    STRCMP,
+    SYSCALL,
+    CALLN,
+    IGL,
 }
 
+/// Everything the assembler, the disassembler and the REPL help know about an opcode.
+struct OpcodeInfo {
+    opcode: Opcode,
+    /// The name the assembler knows the opcode by.
+    mnemonic: &'static str,
+    /// Operands encoded after the opcode byte, in order.
+    operands: &'static [Operand],
+    /// What the instruction does, in terms of its operands in order.
+    description: &'static str,
+    /// Assembly using the instruction, declaring the labels it needs after it.
+    example: &'static str,
+}
+
+/// Every opcode the VM executes, in the order of their byte values.
+static OPCODES: [OpcodeInfo; Opcode::IGL as usize] = {
+    use self::Operand::*;
+    [
+        OpcodeInfo {
+            opcode: Opcode::HLT,
+            mnemonic: "hlt",
+            operands: &[],
+            description: "Stops the program",
+            example: "hlt",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LOAD,
+            mnemonic: "load",
+            operands: &[Register, Immediate],
+            description: "Loads the 16 bit unsigned value into the register",
+            example: "load $0 #500",
+        },
+        OpcodeInfo {
+            opcode: Opcode::ADD,
+            mnemonic: "add",
+            operands: &[Register, Register, Register],
+            description: "Adds the first two registers and stores the sum in the third",
+            example: "add $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SUB,
+            mnemonic: "sub",
+            operands: &[Register, Register, Register],
+            description: "Subtracts the second register from the first and stores the difference in the third",
+            example: "sub $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::MUL,
+            mnemonic: "mul",
+            operands: &[Register, Register, Register],
+            description: "Multiplies the first two registers and stores the product in the third",
+            example: "mul $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::DIV,
+            mnemonic: "div",
+            operands: &[Register, Register, Register],
+            description: "Divides the first register by the second, truncating towards zero, stores the quotient in the third and keeps the remainder for MOVREM, stopping the program if the divisor is zero",
+            example: "div $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JMP,
+            mnemonic: "jmp",
+            operands: &[Register],
+            description: "Jumps to the address held in the register",
+            example: "jmp $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JMPF,
+            mnemonic: "jmpf",
+            operands: &[Register],
+            description: "Jumps forward by the number of bytes held in the register, counted from the end of the instruction",
+            example: "jmpf $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JMPB,
+            mnemonic: "jmpb",
+            operands: &[Register],
+            description: "Jumps backward by the number of bytes held in the register, counted from the end of the instruction",
+            example: "jmpb $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::EQ,
+            mnemonic: "eq",
+            operands: &[Register, Register],
+            description: "Sets the equal flag if the two registers are equal, clears it otherwise",
+            example: "eq $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::NEQ,
+            mnemonic: "neq",
+            operands: &[Register, Register],
+            description: "Sets the equal flag if the two registers differ, clears it otherwise",
+            example: "neq $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::GT,
+            mnemonic: "gt",
+            operands: &[Register, Register],
+            description: "Sets the equal flag if the first register is greater than the second, clears it otherwise",
+            example: "gt $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LT,
+            mnemonic: "lt",
+            operands: &[Register, Register],
+            description: "Sets the equal flag if the first register is less than the second, clears it otherwise",
+            example: "lt $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::GTQ,
+            mnemonic: "gte",
+            operands: &[Register, Register],
+            description: "Sets the equal flag if the first register is greater than or equal to the second, clears it otherwise",
+            example: "gte $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LTQ,
+            mnemonic: "lte",
+            operands: &[Register, Register],
+            description: "Sets the equal flag if the first register is less than or equal to the second, clears it otherwise",
+            example: "lte $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JEQ,
+            mnemonic: "jeq",
+            operands: &[Register],
+            description: "Jumps to the address held in the register if the equal flag is set",
+            example: "jeq $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JNEQ,
+            mnemonic: "jneq",
+            operands: &[Register],
+            description: "Jumps to the address held in the register if the equal flag is clear",
+            example: "jneq $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::ALOC,
+            mnemonic: "aloc",
+            operands: &[Register],
+            description: "Grows the heap by the number of zero bytes held in the register",
+            example: "aloc $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::AND,
+            mnemonic: "and",
+            operands: &[Register, Register, Register],
+            description: "Stores the bitwise AND of the first two registers in the third",
+            example: "and $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::OR,
+            mnemonic: "or",
+            operands: &[Register, Register, Register],
+            description: "Stores the bitwise OR of the first two registers in the third",
+            example: "or $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::XOR,
+            mnemonic: "xor",
+            operands: &[Register, Register, Register],
+            description: "Stores the bitwise XOR of the first two registers in the third",
+            example: "xor $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::NOT,
+            mnemonic: "not",
+            operands: &[Register, Register],
+            description: "Stores the bitwise NOT of the first register in the second",
+            example: "not $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SHL,
+            mnemonic: "shl",
+            operands: &[Register, Register, Register],
+            description: "Shifts the first register left by the low 5 bits of the second and stores the result in the third",
+            example: "shl $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SHR,
+            mnemonic: "shr",
+            operands: &[Register, Register, Register],
+            description: "Shifts the first register right, filling with zeros, by the low 5 bits of the second and stores the result in the third",
+            example: "shr $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SAR,
+            mnemonic: "sar",
+            operands: &[Register, Register, Register],
+            description: "Shifts the first register right, keeping its sign, by the low 5 bits of the second and stores the result in the third",
+            example: "sar $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::MOVREM,
+            mnemonic: "movrem",
+            operands: &[Register],
+            description: "Stores the remainder left by the last DIV in the register",
+            example: "movrem $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::MOD,
+            mnemonic: "mod",
+            operands: &[Register, Register, Register],
+            description: "Stores the Euclidean modulo of the first register by the second, never negative, in the third, stopping the program if the divisor is zero",
+            example: "mod $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::MOV,
+            mnemonic: "mov",
+            operands: &[Register, Register],
+            description: "Copies the first register into the second",
+            example: "mov $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::ADDI,
+            mnemonic: "addi",
+            operands: &[Register, SignedImmediate, Register],
+            description: "Adds the value to the first register and stores the sum in the second",
+            example: "addi $0 #-3 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SUBI,
+            mnemonic: "subi",
+            operands: &[Register, SignedImmediate, Register],
+            description: "Subtracts the value from the first register and stores the difference in the second",
+            example: "subi $0 #1 $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::MULI,
+            mnemonic: "muli",
+            operands: &[Register, SignedImmediate, Register],
+            description: "Multiplies the first register by the value and stores the product in the second",
+            example: "muli $0 #10 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::EQI,
+            mnemonic: "eqi",
+            operands: &[Register, SignedImmediate],
+            description: "Sets the equal flag if the register equals the value, clears it otherwise",
+            example: "eqi $0 #0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::NEQI,
+            mnemonic: "neqi",
+            operands: &[Register, SignedImmediate],
+            description: "Sets the equal flag if the register differs from the value, clears it otherwise",
+            example: "neqi $0 #0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::GTI,
+            mnemonic: "gti",
+            operands: &[Register, SignedImmediate],
+            description: "Sets the equal flag if the register is greater than the value, clears it otherwise",
+            example: "gti $0 #100",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LTI,
+            mnemonic: "lti",
+            operands: &[Register, SignedImmediate],
+            description: "Sets the equal flag if the register is less than the value, clears it otherwise",
+            example: "lti $0 #100",
+        },
+        OpcodeInfo {
+            opcode: Opcode::GTQI,
+            mnemonic: "gtei",
+            operands: &[Register, SignedImmediate],
+            description: "Sets the equal flag if the register is greater than or equal to the value, clears it otherwise",
+            example: "gtei $0 #100",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LTQI,
+            mnemonic: "ltei",
+            operands: &[Register, SignedImmediate],
+            description: "Sets the equal flag if the register is less than or equal to the value, clears it otherwise",
+            example: "ltei $0 #100",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SEQ,
+            mnemonic: "seq",
+            operands: &[Register, Register, Register],
+            description: "Stores 1 in the third register if the first two are equal, 0 otherwise",
+            example: "seq $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SNEQ,
+            mnemonic: "sneq",
+            operands: &[Register, Register, Register],
+            description: "Stores 1 in the third register if the first two differ, 0 otherwise",
+            example: "sneq $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SGT,
+            mnemonic: "sgt",
+            operands: &[Register, Register, Register],
+            description: "Stores 1 in the third register if the first is greater than the second, 0 otherwise",
+            example: "sgt $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SLT,
+            mnemonic: "slt",
+            operands: &[Register, Register, Register],
+            description: "Stores 1 in the third register if the first is less than the second, 0 otherwise",
+            example: "slt $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SGTQ,
+            mnemonic: "sgte",
+            operands: &[Register, Register, Register],
+            description: "Stores 1 in the third register if the first is greater than or equal to the second, 0 otherwise",
+            example: "sgte $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SLTQ,
+            mnemonic: "slte",
+            operands: &[Register, Register, Register],
+            description: "Stores 1 in the third register if the first is less than or equal to the second, 0 otherwise",
+            example: "slte $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JMPI,
+            mnemonic: "jmpi",
+            operands: &[Address],
+            description: "Jumps to the address",
+            example: "jmpi #0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JEQI,
+            mnemonic: "jeqi",
+            operands: &[Address],
+            description: "Jumps to the address if the equal flag is set",
+            example: "jeqi #0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JNEQI,
+            mnemonic: "jneqi",
+            operands: &[Address],
+            description: "Jumps to the address if the equal flag is clear",
+            example: "jneqi #0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JMPR,
+            mnemonic: "jmpr",
+            operands: &[Offset],
+            description: "Jumps by the signed offset, counted from the end of the instruction",
+            example: "jmpr #-3",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JEQR,
+            mnemonic: "jeqr",
+            operands: &[Offset],
+            description: "Jumps by the signed offset, counted from the end of the instruction, if the equal flag is set",
+            example: "jeqr #-3",
+        },
+        OpcodeInfo {
+            opcode: Opcode::JNEQR,
+            mnemonic: "jneqr",
+            operands: &[Offset],
+            description: "Jumps by the signed offset, counted from the end of the instruction, if the equal flag is clear",
+            example: "jneqr #-3",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LOADF,
+            mnemonic: "loadf",
+            operands: &[FloatRegister, Float],
+            description: "Loads the float into the float register",
+            example: "loadf $0 #1.5",
+        },
+        OpcodeInfo {
+            opcode: Opcode::ADDF,
+            mnemonic: "addf",
+            operands: &[FloatRegister, FloatRegister, FloatRegister],
+            description: "Adds the first two float registers and stores the sum in the third",
+            example: "addf $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SUBF,
+            mnemonic: "subf",
+            operands: &[FloatRegister, FloatRegister, FloatRegister],
+            description: "Subtracts the second float register from the first and stores the difference in the third",
+            example: "subf $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::MULF,
+            mnemonic: "mulf",
+            operands: &[FloatRegister, FloatRegister, FloatRegister],
+            description: "Multiplies the first two float registers and stores the product in the third",
+            example: "mulf $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::DIVF,
+            mnemonic: "divf",
+            operands: &[FloatRegister, FloatRegister, FloatRegister],
+            description: "Divides the first float register by the second and stores the quotient in the third",
+            example: "divf $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::EQF,
+            mnemonic: "eqf",
+            operands: &[FloatRegister, FloatRegister],
+            description: "Sets the equal flag if the two float registers are equal, clears it otherwise",
+            example: "eqf $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::NEQF,
+            mnemonic: "neqf",
+            operands: &[FloatRegister, FloatRegister],
+            description: "Sets the equal flag if the two float registers differ, clears it otherwise",
+            example: "neqf $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::GTF,
+            mnemonic: "gtf",
+            operands: &[FloatRegister, FloatRegister],
+            description: "Sets the equal flag if the first float register is greater than the second, clears it otherwise",
+            example: "gtf $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LTF,
+            mnemonic: "ltf",
+            operands: &[FloatRegister, FloatRegister],
+            description: "Sets the equal flag if the first float register is less than the second, clears it otherwise",
+            example: "ltf $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::GTQF,
+            mnemonic: "gtef",
+            operands: &[FloatRegister, FloatRegister],
+            description: "Sets the equal flag if the first float register is greater than or equal to the second, clears it otherwise",
+            example: "gtef $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LTQF,
+            mnemonic: "ltef",
+            operands: &[FloatRegister, FloatRegister],
+            description: "Sets the equal flag if the first float register is less than or equal to the second, clears it otherwise",
+            example: "ltef $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::ITOF,
+            mnemonic: "itof",
+            operands: &[Register, FloatRegister],
+            description: "Converts the register to a float stored in the float register",
+            example: "itof $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::FTOI,
+            mnemonic: "ftoi",
+            operands: &[FloatRegister, Register],
+            description: "Converts the float register to an integer stored in the register, truncating towards zero and saturating (NaN becomes 0)",
+            example: "ftoi $1 $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::PRTS,
+            mnemonic: "prts",
+            operands: &[RoOffset],
+            description: "Prints the string at the offset in the read-only section",
+            example: "prts @greeting\ngreeting: .asciiz 'Hello'",
+        },
+        OpcodeInfo {
+            opcode: Opcode::PRTH,
+            mnemonic: "prth",
+            operands: &[Register],
+            description: "Prints the heap string whose address is in the register",
+            example: "prth $0",
+        },
+        OpcodeInfo {
+            opcode: Opcode::LDSTR,
+            mnemonic: "ldstr",
+            operands: &[RoOffset, Register],
+            description: "Copies the read-only string to a new heap string and stores its address in the register",
+            example: "ldstr @name $0\nname: .asciiz 'Ada'",
+        },
+        OpcodeInfo {
+            opcode: Opcode::STRCAT,
+            mnemonic: "strcat",
+            operands: &[Register, Register, Register],
+            description: "Concatenates the heap strings whose addresses are in the first two registers into a new one whose address is stored in the third",
+            example: "strcat $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::STRLEN,
+            mnemonic: "strlen",
+            operands: &[Register, Register],
+            description: "Stores the length of the heap string whose address is in the first register in the second",
+            example: "strlen $0 $1",
+        },
+        OpcodeInfo {
+            opcode: Opcode::STRCMP,
+            mnemonic: "strcmp",
+            operands: &[Register, Register, Register],
+            description: "Compares the heap strings whose addresses are in the first two registers byte by byte, storing -1, 0 or 1 in the third",
+            example: "strcmp $0 $1 $2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::SYSCALL,
+            mnemonic: "syscall",
+            operands: &[Syscall],
+            description: "Calls the host: 0 writes the heap string at $1, 1 reads a line into a heap string whose address goes in $0, 2 stores a clock in milliseconds in $0, 3 exits with the code in $1",
+            example: "syscall #2",
+        },
+        OpcodeInfo {
+            opcode: Opcode::CALLN,
+            mnemonic: "calln",
+            operands: &[Native],
+            description: "Calls a native function registered by the host with the registers as arguments, storing its result in $0",
+            example: "calln @max $1 $2",
+        },
+    ]
+};
+
+/// Stands for every byte that isn't a valid opcode.
+static ILLEGAL: OpcodeInfo = OpcodeInfo {
+    opcode: Opcode::IGL,
+    mnemonic: "igl",
+    operands: &[],
+    description: "An opcode the VM doesn't know, stopping the program",
+    example: "",
+};
+
 impl From<u8> for Opcode {
     fn from(v: u8) -> Self {
-        match v {
-            0 => Opcode::HLT,
-            1 => Opcode::LOAD,
-            2 => Opcode::ADD,
-            3 => Opcode::SUB,
-            4 => Opcode::MUL,
-            5 => Opcode::DIV,
-            6 => Opcode::JMP,
-            7 => Opcode::JMPF,
-            8 => Opcode::JMPB,
-            9 => Opcode::EQ,
-            10 => Opcode::NEQ,
-            11 => Opcode::GT,
-            12 => Opcode::LT,
-            13 => Opcode::GTQ,
-            14 => Opcode::LTQ,
-            15 => Opcode::JEQ,
-            16 => Opcode::JNEQ,
-            17 => Opcode::ALOC,
-            _ => Opcode::IGL,
-        }
+        OPCODES.get(v as usize).map_or(Opcode::IGL, |info| info.opcode)
     }
 }
 
 impl<'a> From<CompleteStr<'a>> for Opcode {
     fn from(v: CompleteStr<'a>) -> Self {
-        match v {
-            CompleteStr("load") => Opcode::LOAD,
-            CompleteStr("add") => Opcode::ADD,
-            CompleteStr("sub") => Opcode::SUB,
-            CompleteStr("mul") => Opcode::MUL,
-            CompleteStr("div") => Opcode::DIV,
-            CompleteStr("hlt") => Opcode::HLT,
-            CompleteStr("jmp") => Opcode::JMP,
-            CompleteStr("jmpf") => Opcode::JMPF,
-            CompleteStr("jmpb") => Opcode::JMPB,
-            CompleteStr("eq") => Opcode::EQ,
-            CompleteStr("neq") => Opcode::NEQ,
-            CompleteStr("gte") => Opcode::GTQ,
-            CompleteStr("gt") => Opcode::GT,
-            CompleteStr("lte") => Opcode::LTQ,
-            CompleteStr("lt") => Opcode::LT,
-            CompleteStr("jeq") => Opcode::JEQ,
-            CompleteStr("jneq") => Opcode::JNEQ,
-            CompleteStr("aloc") => Opcode::ALOC,
-            _ => Opcode::IGL,
+        Opcode::all()
+            .find(|opcode| opcode.mnemonic() == v.0)
+            .unwrap_or(Opcode::IGL)
+    }
+}
+
+impl Opcode {
+    /// Every valid opcode, in the order of their byte values, leaving out IGL.
+    pub fn all() -> impl Iterator<Item = Opcode> {
+        OPCODES.iter().map(|info| info.opcode)
+    }
+
+    fn info(self) -> &'static OpcodeInfo {
+        OPCODES.get(self as usize).unwrap_or(&ILLEGAL)
+    }
+
+    /// Returns the variant of this opcode that takes a 16 bit signed immediate in place of
+    /// its last source register, if there is one.
+    pub fn immediate_form(self) -> Option<Opcode> {
+        match self {
+            Opcode::ADD => Some(Opcode::ADDI),
+            Opcode::SUB => Some(Opcode::SUBI),
+            Opcode::MUL => Some(Opcode::MULI),
+            Opcode::EQ => Some(Opcode::EQI),
+            Opcode::NEQ => Some(Opcode::NEQI),
+            Opcode::GT => Some(Opcode::GTI),
+            Opcode::LT => Some(Opcode::LTI),
+            Opcode::GTQ => Some(Opcode::GTQI),
+            Opcode::LTQ => Some(Opcode::LTQI),
+            _ => None,
+        }
+    }
+
+    /// Returns the variant of a comparison that writes its result (1 or 0) into a destination
+    /// register instead of the equal flag, if there is one.
+    pub fn register_form(self) -> Option<Opcode> {
+        match self {
+            Opcode::EQ => Some(Opcode::SEQ),
+            Opcode::NEQ => Some(Opcode::SNEQ),
+            Opcode::GT => Some(Opcode::SGT),
+            Opcode::LT => Some(Opcode::SLT),
+            Opcode::GTQ => Some(Opcode::SGTQ),
+            Opcode::LTQ => Some(Opcode::SLTQ),
+            _ => None,
+        }
+    }
+
+    /// Operands encoded after the opcode byte, in order.
+    pub fn operands(self) -> &'static [Operand] {
+        self.info().operands
+    }
+
+    /// The name the assembler knows the opcode by.
+    pub fn mnemonic(self) -> &'static str {
+        self.info().mnemonic
+    }
+
+    /// What the instruction does, in terms of its operands in order.
+    pub fn description(self) -> &'static str {
+        self.info().description
+    }
+
+    /// Assembly using the instruction, declaring the labels it needs after it.
+    pub fn example(self) -> &'static str {
+        self.info().example
+    }
+
+    /// Returns the `(relative, absolute)` variants of a jump that encode their target in the
+    /// instruction itself instead of reading it from a register, if there are any.
+    pub fn branch_forms(self) -> Option<(Opcode, Opcode)> {
+        match self {
+            Opcode::JMP => Some((Opcode::JMPR, Opcode::JMPI)),
+            Opcode::JEQ => Some((Opcode::JEQR, Opcode::JEQI)),
+            Opcode::JNEQ => Some((Opcode::JNEQR, Opcode::JNEQI)),
+            _ => None,
         }
     }
 }
@@ -109,4 +783,106 @@ mod tests {
         let opcode = Opcode::from(CompleteStr("illegal"));
         assert_eq!(opcode, Opcode::IGL);
     }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for (mnemonic, opcode) in [
+            ("aloc", Opcode::ALOC),
+            ("and", Opcode::AND),
+            ("or", Opcode::OR),
+            ("xor", Opcode::XOR),
+            ("not", Opcode::NOT),
+            ("shl", Opcode::SHL),
+            ("shr", Opcode::SHR),
+            ("sar", Opcode::SAR),
+            ("movrem", Opcode::MOVREM),
+            ("mod", Opcode::MOD),
+            ("mov", Opcode::MOV),
+            ("addi", Opcode::ADDI),
+            ("subi", Opcode::SUBI),
+            ("muli", Opcode::MULI),
+            ("eqi", Opcode::EQI),
+            ("neqi", Opcode::NEQI),
+            ("gti", Opcode::GTI),
+            ("lti", Opcode::LTI),
+            ("gtei", Opcode::GTQI),
+            ("ltei", Opcode::LTQI),
+            ("seq", Opcode::SEQ),
+            ("sneq", Opcode::SNEQ),
+            ("sgt", Opcode::SGT),
+            ("slt", Opcode::SLT),
+            ("sgte", Opcode::SGTQ),
+            ("slte", Opcode::SLTQ),
+            ("jmpi", Opcode::JMPI),
+            ("jeqi", Opcode::JEQI),
+            ("jneqi", Opcode::JNEQI),
+            ("jmpr", Opcode::JMPR),
+            ("jeqr", Opcode::JEQR),
+            ("jneqr", Opcode::JNEQR),
+            ("loadf", Opcode::LOADF),
+            ("addf", Opcode::ADDF),
+            ("subf", Opcode::SUBF),
+            ("mulf", Opcode::MULF),
+            ("divf", Opcode::DIVF),
+            ("eqf", Opcode::EQF),
+            ("neqf", Opcode::NEQF),
+            ("gtf", Opcode::GTF),
+            ("ltf", Opcode::LTF),
+            ("gtef", Opcode::GTQF),
+            ("ltef", Opcode::LTQF),
+            ("itof", Opcode::ITOF),
+            ("ftoi", Opcode::FTOI),
+            ("prts", Opcode::PRTS),
+            ("prth", Opcode::PRTH),
+            ("ldstr", Opcode::LDSTR),
+            ("strcat", Opcode::STRCAT),
+            ("strlen", Opcode::STRLEN),
+            ("strcmp", Opcode::STRCMP),
+            ("syscall", Opcode::SYSCALL),
+            ("calln", Opcode::CALLN),
+        ] {
+            let parsed = Opcode::from(CompleteStr(mnemonic));
+            assert_eq!(parsed, opcode);
+            assert_eq!(Opcode::from(parsed as u8), opcode);
+            assert_eq!(opcode.mnemonic(), mnemonic);
+        }
+    }
+
+    #[test]
+    fn test_immediate_form() {
+        assert_eq!(Opcode::ADD.immediate_form(), Some(Opcode::ADDI));
+        assert_eq!(Opcode::GTQ.immediate_form(), Some(Opcode::GTQI));
+        assert_eq!(Opcode::LOAD.immediate_form(), None);
+        assert_eq!(Opcode::ADDI.immediate_form(), None);
+    }
+
+    #[test]
+    fn test_register_form() {
+        assert_eq!(Opcode::EQ.register_form(), Some(Opcode::SEQ));
+        assert_eq!(Opcode::LTQ.register_form(), Some(Opcode::SLTQ));
+        assert_eq!(Opcode::ADD.register_form(), None);
+    }
+
+    #[test]
+    fn test_every_example_assembles_to_its_opcode() {
+        let mut vm = crate::vm::VM::new();
+        vm.register_native("max", |_, args| Ok(args[0].max(args[1])));
+        for opcode in Opcode::all() {
+            assert!(!opcode.description().is_empty());
+            let program = crate::assembler::Assembler::for_vm(&vm)
+                .assemble(opcode.example())
+                .unwrap();
+            assert_eq!(program[0], opcode as u8, "example of {}", opcode.mnemonic());
+        }
+    }
+
+    #[test]
+    fn test_every_opcode_has_a_mnemonic() {
+        for (byte, opcode) in Opcode::all().enumerate() {
+            assert_eq!(opcode as usize, byte);
+            assert_ne!(opcode, Opcode::IGL);
+            assert_eq!(Opcode::from(CompleteStr(opcode.mnemonic())), opcode);
+        }
+        assert_eq!(Opcode::all().count(), Opcode::IGL as usize);
+    }
 }
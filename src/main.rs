@@ -5,7 +5,91 @@ pub mod instruction;
 pub mod repl;
 pub mod assembler;
 
-fn main() {
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal};
+use std::process;
+
+const USAGE: &str = "Usage: vanadium [repl [--script <file>]]";
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
     let mut repl = repl::REPL::new();
-    repl.run();
+    match args.as_slice() {
+        [] | ["repl"] if io::stdin().is_terminal() => repl.run_interactive(),
+        [] | ["repl"] => repl.run(io::stdin().lock()),
+        ["repl", "--script", path] => {
+            let code = run_script(&mut repl, path)?;
+            if code != 0 {
+                process::exit(code);
+            }
+            Ok(())
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Runs the script at `path` and returns the exit status: 1 if it can't be read or any of its
+/// lines failed, 0 otherwise.
+fn run_script(repl: &mut repl::REPL, path: &str) -> io::Result<i32> {
+    let script = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            eprintln!("Unable to read {}: {}", path, e);
+            return Ok(1);
+        }
+    };
+    let failed = repl.run_script(script)?;
+    if failed.is_empty() {
+        return Ok(0);
+    }
+    let lines: Vec<String> = failed.iter().map(|line| line.to_string()).collect();
+    eprintln!("{}: lines {} failed", path, lines.join(", "));
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::output::SharedBuffer;
+    use std::fs;
+
+    fn exit_status(name: &str, script: &str) -> i32 {
+        let path = env::temp_dir().join(format!("vanadium_{}_{}.vrepl", name, process::id()));
+        fs::write(&path, script).unwrap();
+        let mut repl = repl::REPL::with_output(Box::new(SharedBuffer::new()));
+        let code = run_script(&mut repl, path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        code
+    }
+
+    #[test]
+    fn test_script_exit_status() {
+        assert_eq!(exit_status("clean", "load $0 #1\n.set $1 2\n.flags\n"), 0);
+        assert_eq!(exit_status("parse", "load $0 #1\nload $0\n"), 1);
+        assert_eq!(exit_status("fault", "syscall #99\n"), 1);
+        let dot_commands = [
+            ".set $99 1",
+            ".break nolabel",
+            ".restore /nonexistent/image",
+            ".step abc",
+            ".watch bogus",
+            ".heap x",
+            ".snapshot /nonexistent/image",
+            ".save_program /nonexistent/program",
+            ".trace /nonexistent/trace",
+            ".replay /nonexistent/trace",
+            ".help nosuch",
+            ".end",
+        ];
+        for (i, command) in dot_commands.iter().enumerate() {
+            assert_eq!(exit_status(&format!("command_{}", i), command), 1, "{}", command);
+        }
+        assert_eq!(exit_status("empty", ""), 0);
+        assert_eq!(run_script(&mut repl::REPL::new(), "/nonexistent/script").unwrap(), 1);
+    }
 }
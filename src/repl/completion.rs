@@ -0,0 +1,166 @@
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::instruction::Opcode;
+
+/// Every command the REPL understands, for completion.
+pub const COMMANDS: &[&str] = &[
+    ".block",
+    ".break",
+    ".cancel",
+    ".clear_program",
+    ".clear_registers",
+    ".continue",
+    ".delete",
+    ".end",
+    ".flags",
+    ".heap",
+    ".help",
+    ".help_instruction",
+    ".hex",
+    ".history",
+    ".inspect",
+    ".load",
+    ".load_file",
+    ".pc",
+    ".program",
+    ".quit",
+    ".registers",
+    ".replay",
+    ".reset",
+    ".restore",
+    ".reverse-continue",
+    ".reverse-step",
+    ".rback",
+    ".rstep",
+    ".run",
+    ".save_program",
+    ".set",
+    ".snapshot",
+    ".step",
+    ".trace",
+    ".undo",
+    ".unwatch",
+    ".watch",
+    ".where",
+];
+
+/// Completes the word under the cursor: commands and mnemonics at the start of the line,
+/// mnemonics after `.help`, registers after `$` and labels after `@` or as the location of
+/// `.break` and `.delete`.
+#[derive(Debug, Default)]
+pub struct ReplHelper {
+    /// The labels declared so far, kept up to date by the REPL before each line is read.
+    pub labels: Vec<String>,
+}
+
+impl ReplHelper {
+    fn candidates(&self, line: &str, start: usize) -> Vec<String> {
+        let word = &line[start..];
+        let before = line[..start].trim();
+        let mut candidates: Vec<String> = if before.is_empty() && word.starts_with('.') {
+            COMMANDS.iter().map(|command| command.to_string()).collect()
+        } else if before.is_empty() || before == ".help" {
+            Opcode::all().map(|opcode| opcode.mnemonic().to_string()).collect()
+        } else if word.starts_with('$') {
+            (0..32).map(|register| format!("${}", register)).collect()
+        } else if word.starts_with('@') {
+            self.labels.iter().map(|label| format!("@{}", label)).collect()
+        } else if before == ".break" || before == ".delete" {
+            self.labels.clone()
+        } else {
+            vec![]
+        };
+        candidates.retain(|candidate| candidate.starts_with(word));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |space| space + 1);
+        let candidates = self
+            .candidates(line, start)
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::MemHistory;
+
+    /// Completes `line` with the cursor at `pos`, returning where the replacement starts and
+    /// the replacements offered.
+    fn complete_at(helper: &ReplHelper, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let history = MemHistory::new();
+        let (start, pairs) = helper.complete(line, pos, &Context::new(&history)).unwrap();
+        for pair in &pairs {
+            assert_eq!(pair.display, pair.replacement);
+        }
+        (start, pairs.into_iter().map(|pair| pair.replacement).collect())
+    }
+
+    fn complete(helper: &ReplHelper, line: &str) -> Vec<String> {
+        complete_at(helper, line, line.len()).1
+    }
+
+    #[test]
+    fn test_complete() {
+        let helper = ReplHelper {
+            labels: vec!["loop".to_string(), "end".to_string()],
+        };
+        assert_eq!(complete(&helper, ".rev"), vec![".reverse-continue", ".reverse-step"]);
+        assert_eq!(complete(&helper, "jne"), vec!["jneq", "jneqi", "jneqr"]);
+        assert_eq!(complete(&helper, ".help str"), vec!["strcat", "strcmp", "strlen"]);
+        assert_eq!(complete(&helper, "add $0 $3"), vec!["$3", "$30", "$31"]);
+        assert_eq!(complete(&helper, "jmp @l"), vec!["@loop"]);
+        assert_eq!(complete(&helper, ".break e"), vec!["end"]);
+        assert!(complete(&helper, "load $0 #").is_empty());
+    }
+
+    #[test]
+    fn test_complete_start_and_cursor() {
+        let helper = ReplHelper {
+            labels: vec!["loop".to_string()],
+        };
+        assert_eq!(complete_at(&helper, "jmp @lo", 7), (4, vec!["@loop".to_string()]));
+        let (start, registers) = complete_at(&helper, "add $0 $3", 9);
+        assert_eq!(start, 7);
+        assert_eq!(registers, vec!["$3", "$30", "$31"]);
+        // Only the text before the cursor is completed
+        assert_eq!(complete_at(&helper, "jmp @lo hlt", 7), (4, vec!["@loop".to_string()]));
+        assert_eq!(complete_at(&helper, "lo", 2), (0, vec!["load".to_string(), "loadf".to_string()]));
+        // Tabs separate words too
+        assert_eq!(complete_at(&helper, "jmp\t@l", 6), (4, vec!["@loop".to_string()]));
+    }
+}
@@ -1,13 +1,54 @@
-use crate::assembler::program_parser::program;
-
-use super::vm::VM;
-use std;
-use std::io::{self, Write};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
 use std::num::ParseIntError;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use nom::types::CompleteStr;
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use crate::assembler::disassembler::{disassemble, disassemble_program, DecodedOperand};
+use crate::assembler::{Assembler, AssemblerError};
+use crate::instruction::Opcode;
+
+use super::vm::output::SharedWriter;
+use super::vm::syscall::{DefaultSyscalls, InputSource};
+use super::vm::trace::{RegisterChange, Replay};
+use super::vm::watchpoint::Watchpoint;
+use super::vm::{HaltReason, StopReason, VM};
+
+use self::completion::ReplHelper;
+
+mod completion;
+
+/// How often the VM checkpoints its whole state for `.reverse-step` and `.reverse-continue`.
+const HISTORY_CHECKPOINT_INTERVAL: usize = 64;
+/// Roughly how many instructions can be stepped back over.
+const HISTORY_CAPACITY: usize = 100_000;
+/// File in the home directory the lines typed in interactive sessions are kept in.
+const HISTORY_FILE: &str = ".vanadium_history";
 
 pub struct REPL {
     command_buffer: Vec<String>,
     vm: VM,
+    replay: Option<Replay>,
+    /// Addresses of the labels declared so far, for `.break` and `.where`.
+    labels: HashMap<String, usize>,
+    /// Where each block of code appended to the program started, in the program and the
+    /// read-only data, for `.undo`.
+    blocks: Vec<(usize, usize)>,
+    /// The lines typed since `.block`, assembled together on `.end`.
+    pending_block: Option<Vec<String>>,
+    /// Whether lines are read as hexadecimal bytecode instead of assembly, toggled by `.hex`.
+    hex_mode: bool,
+    /// Number of lines that couldn't be parsed or made the VM fault, for `run_script`.
+    failures: usize,
+    /// Where the REPL and the VM it drives both write.
+    output: SharedWriter,
 }
 
 impl Default for REPL {
@@ -17,106 +58,865 @@ impl Default for REPL {
 }
 
 impl REPL {
+    /// Creates a REPL writing to stdout.
     pub fn new() -> REPL {
+        REPL::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates a REPL writing everything, its own messages and the output of the programs it
+    /// runs, to `output`.
+    pub fn with_output(output: Box<dyn Write>) -> REPL {
+        let output = SharedWriter::new(output);
+        let mut vm = VM::new();
+        vm.set_output(Box::new(output.clone()));
+        vm.enable_history(HISTORY_CHECKPOINT_INTERVAL, HISTORY_CAPACITY);
         REPL {
             command_buffer: vec![],
-            vm: VM::new(),
+            vm,
+            replay: None,
+            labels: HashMap::new(),
+            blocks: vec![],
+            pending_block: None,
+            hex_mode: false,
+            failures: 0,
+            output,
         }
     }
 
-    pub fn run(&mut self) {
-        println!("Welcome to Vanadium! This is a REPL !");
+    /// Reads lines from `input` and executes them until `.quit` or the end of the input.
+    /// Programs reading a line with `SYS_READ_LINE` get the next line of `input`.
+    pub fn run<R: BufRead + 'static>(&mut self, input: R) -> io::Result<()> {
+        let input = self.share_input(input);
+        writeln!(self.output, "Welcome to Vanadium! This is a REPL !")?;
         loop {
+            write!(self.output, "{}", self.prompt())?;
+            self.output.flush()?;
+
             let mut buffer = String::new();
-            let stdin = io::stdin();
+            if input.read_line(&mut buffer)? == 0 {
+                writeln!(self.output)?;
+                return Ok(());
+            }
+            if !self.execute_line(buffer.trim())? {
+                return Ok(());
+            }
+        }
+    }
 
-            print!(">> ");
-            io::stdout().flush().expect("Unable to flush stdout");
+    /// Reads lines from the terminal, with line editing, reverse search, tab completion and
+    /// a history kept across sessions, and executes them until `.quit` or end of input.
+    pub fn run_interactive(&mut self) -> io::Result<()> {
+        let mut editor = Editor::new().map_err(readline_error)?;
+        editor.set_helper(Some(ReplHelper::default()));
+        let history_path = history_path();
+        if let Some(path) = &history_path {
+            // There is no history yet on the first session
+            let _ = editor.load_history(path);
+        }
+        writeln!(self.output, "Welcome to Vanadium! This is a REPL !")?;
+        loop {
+            self.output.flush()?;
+            if let Some(helper) = editor.helper_mut() {
+                helper.labels = self.labels.keys().cloned().collect();
+            }
+            let line = match editor.readline(self.prompt()) {
+                Ok(line) => line,
+                // Ctrl-C abandons the line being typed
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(readline_error(e)),
+            };
+            if !line.trim().is_empty() {
+                editor.add_history_entry(line.trim()).map_err(readline_error)?;
+            }
+            if !self.execute_line(line.trim())? {
+                break;
+            }
+        }
+        if let Some(path) = &history_path {
+            if let Err(e) = editor.save_history(path) {
+                self.fail(format_args!("Unable to save the history to {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(())
+    }
 
-            stdin
-                .read_line(&mut buffer)
-                .expect("Unable to read line from user");
+    /// Executes the lines of a script until `.quit` or its end, echoing each one after the
+    /// prompt. Returns the numbers of the lines that couldn't be parsed or made the VM fault,
+    /// with a `.block` left open counting as a failure of the line starting it.
+    pub fn run_script<R: BufRead + 'static>(&mut self, input: R) -> io::Result<Vec<usize>> {
+        let input = self.share_input(input);
+        let mut failed = vec![];
+        let mut block_start = None;
+        let mut line = String::new();
+        for index in 0.. {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                break;
+            }
+            writeln!(self.output, "{}{}", self.prompt(), line.trim())?;
+            let failures = self.failures;
+            let in_block = self.pending_block.is_some();
+            let keep_going = self.execute_line(line.trim())?;
+            if self.failures > failures {
+                failed.push(index + 1);
+            }
+            if !in_block && self.pending_block.is_some() {
+                block_start = Some(index + 1);
+            }
+            if !keep_going {
+                break;
+            }
+        }
+        if let (Some(_), Some(line)) = (self.pending_block.take(), block_start) {
+            writeln!(self.output, "The .block started on line {} is never ended", line)?;
+            failed.push(line);
+            failed.sort_unstable();
+        }
+        self.output.flush()?;
+        Ok(failed)
+    }
 
-            let buffer = buffer.trim();
+    /// Makes `SYS_READ_LINE` read from `input`, returning a handle the REPL reads its own lines
+    /// from, so both consume the same stream.
+    fn share_input<R: BufRead + 'static>(&mut self, input: R) -> InputSource {
+        let input = InputSource::new(Box::new(input));
+        self.vm
+            .set_syscall_handler(Some(Box::new(DefaultSyscalls::with_input(input.clone()))));
+        input
+    }
 
-            self.command_buffer.push(buffer.to_string());
-            match buffer {
-                ".quit" => {
-                    println!("Goodbye! We hope you had fun!");
-                    std::process::exit(0);
+    /// Executes one line of input, a command or assembly. Returns `false` once the REPL
+    /// should stop. Blank lines and `#` comments are skipped.
+    fn execute_line(&mut self, buffer: &str) -> io::Result<bool> {
+        if buffer.is_empty() || buffer.starts_with('#') {
+            return Ok(true);
+        }
+        self.command_buffer.push(buffer.to_string());
+        if let Some(lines) = self.pending_block.as_mut() {
+            if buffer != ".end" && buffer != ".cancel" && buffer != ".quit" {
+                lines.push(buffer.to_string());
+                return Ok(true);
+            }
+        }
+        let (command, argument) = match buffer.find(' ') {
+            Some(space) => (&buffer[..space], buffer[space..].trim()),
+            None => (buffer, ""),
+        };
+        match buffer {
+            ".quit" => {
+                // Quitting abandons a block being entered
+                self.pending_block = None;
+                writeln!(self.output, "Goodbye! We hope you had fun!")?;
+                return Ok(false);
+            }
+            ".history" => {
+                for command in &self.command_buffer {
+                    writeln!(self.output, "{}", command)?;
+                }
+            }
+            ".program" => {
+                writeln!(self.output, "Listing instructions currently in VM's program vector:")?;
+                self.vm.display_program_as_hex()?;
+                writeln!(self.output, "End of Program Listing")?;
+            }
+            ".registers" => {
+                writeln!(self.output, "Listing registers and all contents:")?;
+                self.vm.display_registers_square()?;
+                writeln!(self.output, "Float registers:")?;
+                self.vm.display_float_registers_square()?;
+                writeln!(self.output, "End of Register Listing")?;
+            }
+            ".help" => {
+                writeln!(self.output, "Vanadium REPL Help")?;
+                writeln!(self.output, ".help [<mnemonic>] - Show this help message, or how an instruction works")?;
+                writeln!(self.output, ".quit - Quit the REPL")?;
+                writeln!(self.output, ".history - Show command history")?;
+                writeln!(self.output, ".program - Show the program in the VM")?;
+                writeln!(self.output, ".registers - Show the contents of the registers")?;
+                writeln!(self.output, ".inspect - Show the VM state")?;
+                writeln!(self.output, ".heap [<address> [<length>]] - Dump the heap in hexadecimal and ASCII, from address 0 to its end by default")?;
+                writeln!(self.output, ".flags - Show the equal flag and the remainder")?;
+                writeln!(self.output, ".pc - Show the program counter")?;
+                writeln!(self.output, ".set $<register> <value> - Set a register, a float register when value has a decimal point")?;
+                writeln!(self.output, ".help_instruction - Show the Vanadium instruction set")?;
+                writeln!(self.output, ".load <file> - Assemble a file and append it to the program without running it")?;
+                writeln!(self.output, ".load_file <file> - Same as .load")?;
+                writeln!(self.output, ".save_program <file> - Write the program as bytecode, or as assembly if file ends in .iasm")?;
+                writeln!(self.output, ".clear_program - Remove the whole program")?;
+                writeln!(self.output, ".clear_registers - Set every register to zero")?;
+                writeln!(self.output, ".reset - Move back to the start of the program and empty the heap and flags")?;
+                writeln!(self.output, ".undo - Remove the last block of code added to the program")?;
+                writeln!(self.output, ".block - Start a block of lines assembled together, so labels can span lines")?;
+                writeln!(self.output, ".end - Assemble the block and run it")?;
+                writeln!(self.output, ".cancel - Abandon the block without assembling it")?;
+                writeln!(self.output, ".hex - Switch between reading assembly and hexadecimal bytecode")?;
+                writeln!(self.output, "!<bytes> - Run hexadecimal bytecode, e.g. !01 00 03 E8")?;
+                writeln!(self.output, ".run - Execute the program from the current instruction until it stops")?;
+                writeln!(self.output, ".break [<address>|<label>] - Set a breakpoint, or list them without an argument")?;
+                writeln!(self.output, ".delete [<address>|<label>] - Remove a breakpoint, or all of them without an argument")?;
+                writeln!(self.output, ".step [n] - Execute the next n instructions (default 1)")?;
+                writeln!(self.output, ".continue - Run until a breakpoint or the end of the program")?;
+                writeln!(self.output, ".watch [$<register> [<comparison> <value>]] - Stop when the register changes (and the comparison holds), or list watchpoints")?;
+                writeln!(self.output, ".watch read|write <address> [<length>] - Stop when the heap bytes are read or written")?;
+                writeln!(self.output, ".unwatch [<id>] - Remove a watchpoint, or all of them without an argument")?;
+                writeln!(self.output, ".reverse-step [n] - Undo the last n instructions (default 1)")?;
+                writeln!(self.output, ".reverse-continue - Undo instructions until the previous breakpoint")?;
+                writeln!(self.output, ".where - Show the instruction about to be executed")?;
+                writeln!(self.output, ".snapshot <file> - Save the state of the VM to file")?;
+                writeln!(self.output, ".restore <file> - Replace the state of the VM with the one saved in file")?;
+                writeln!(self.output, ".trace <file> - Record every instruction executed from now on to file")?;
+                writeln!(self.output, ".trace off - Stop recording the trace")?;
+                writeln!(self.output, ".replay <file> - Load a trace to step through without executing it")?;
+                writeln!(self.output, ".rstep [n] - Replay the next n instructions of the trace (default 1)")?;
+                writeln!(self.output, ".rback [n] - Step the replay back n instructions (default 1)")?;
+            }
+            _ if command == ".heap" => self.show_heap(argument)?,
+            ".flags" => {
+                writeln!(self.output, "equal_flag: {}", self.vm.equal_flag())?;
+                writeln!(self.output, "remainder: {}", self.vm.remainder())?;
+            }
+            ".pc" => writeln!(self.output, "pc: {}", self.describe_address(self.vm.pc()))?,
+            _ if command == ".set" => self.set_register(argument)?,
+            ".inspect" => self.inspect()?,
+            ".help_instruction" => {
+                writeln!(self.output, "Vanadium Instruction Set")?;
+                for opcode in Opcode::all() {
+                    writeln!(self.output, "{} - {}", Self::syntax(opcode), opcode.description())?;
+                }
+                writeln!(self.output, "<label>: .asciiz '<text>' - Store a NUL-terminated string in the read-only section")?;
+                writeln!(self.output, "Use .help <mnemonic> to see how an instruction is encoded, with an example")?;
+                writeln!(self.output, "End of Instruction Set")?;
+            }
+            _ if command == ".help" => self.help_instruction(argument)?,
+            _ if command == ".load" || command == ".load_file" => self.load(argument)?,
+            _ if command == ".save_program" => self.save_program(argument)?,
+            ".run" => {
+                let reason = self.vm.run();
+                self.note_halt(&reason);
+            }
+            ".block" => {
+                self.pending_block = Some(vec![]);
+                writeln!(self.output, "Enter the block, then .end to assemble and run it")?;
+            }
+            ".hex" => {
+                self.hex_mode = !self.hex_mode;
+                if self.hex_mode {
+                    writeln!(self.output, "Hex mode on, type bytecode such as `01 00 03 E8` and .hex to leave")?;
+                } else {
+                    writeln!(self.output, "Hex mode off")?;
+                }
+            }
+            _ if buffer.starts_with('!') => self.run_hex(&buffer[1..])?,
+            _ if self.hex_mode && !buffer.starts_with('.') => self.run_hex(buffer)?,
+            ".end" => match self.pending_block.take() {
+                Some(lines) => self.run_block(&lines.join("\n"))?,
+                None => self.fail(format_args!("No block to end, start one with .block"))?,
+            },
+            ".cancel" => match self.pending_block.take() {
+                Some(_) => writeln!(self.output, "Block abandoned")?,
+                None => self.fail(format_args!("No block to cancel"))?,
+            },
+            ".clear_program" => {
+                self.vm.clear_program();
+                self.labels.clear();
+                self.blocks.clear();
+                writeln!(self.output, "Program cleared")?;
+            }
+            ".clear_registers" => {
+                self.vm.clear_registers();
+                writeln!(self.output, "Registers cleared")?;
+            }
+            ".reset" => {
+                self.vm.reset();
+                writeln!(self.output, "VM reset")?;
+            }
+            ".undo" => self.undo()?,
+            _ if command == ".break" => self.set_breakpoint(argument)?,
+            _ if command == ".delete" => self.delete_breakpoint(argument)?,
+            _ if command == ".step" => match Self::parse_count(argument) {
+                Some(count) => {
+                    let reason = self.vm.step(count);
+                    self.report_stop(reason)?;
+                }
+                None => self.fail(format_args!("Expected a number of steps, got `{}`", argument))?,
+            },
+            ".continue" => {
+                let reason = self.vm.run_until_breakpoint();
+                self.report_stop(reason)?;
+            }
+            ".where" => self.show_where()?,
+            _ if command == ".reverse-step" => match Self::parse_count(argument) {
+                Some(count) => {
+                    let reason = self.vm.reverse_step(count);
+                    self.report_stop(reason)?;
+                }
+                None => self.fail(format_args!("Expected a number of steps, got `{}`", argument))?,
+            },
+            ".reverse-continue" => {
+                let reason = self.vm.reverse_continue();
+                self.report_stop(reason)?;
+            }
+            _ if command == ".watch" => self.watch(argument)?,
+            _ if command == ".unwatch" => self.unwatch(argument)?,
+            _ if command == ".snapshot" => match fs::write(argument, self.vm.snapshot()) {
+                Ok(()) => writeln!(self.output, "VM state saved to {}", argument)?,
+                Err(e) => self.fail(format_args!("Unable to write {}: {}", argument, e))?,
+            },
+            _ if command == ".restore" => self.restore(argument)?,
+            _ if command == ".trace" => self.trace(argument)?,
+            _ if command == ".replay" => match Replay::load(argument) {
+                Ok(replay) => {
+                    writeln!(self.output, "Loaded a trace of {} instructions", replay.len())?;
+                    self.replay = Some(replay);
                 }
-                ".history" => {
-                    for command in &self.command_buffer {
-                        println!("{}", command);
+                Err(e) => self.fail(format_args!("Unable to load trace: {}", e))?,
+            },
+            _ if command == ".rstep" => self.replay_steps(argument, true)?,
+            _ if command == ".rback" => self.replay_steps(argument, false)?,
+            _ => match self.assemble(buffer) {
+                Ok(_) => match self.vm.step(1) {
+                    StopReason::Watchpoint(hit) => self.report_stop(StopReason::Watchpoint(hit))?,
+                    StopReason::Halted(reason) => self.note_halt(&reason),
+                    _ => {}
+                },
+                Err(e) => self.fail(format_args!("Unable to parse input: {}", e))?,
+            },
+        }
+        Ok(true)
+    }
+
+    /// Assembles `source`, appending its code and data to the VM's, and returns the address
+    /// the code was loaded at.
+    fn assemble(&mut self, source: &str) -> Result<usize, AssemblerError> {
+        let mut assembler = Assembler::for_vm(&self.vm);
+        let mut bytes = assembler.assemble(source)?;
+        let origin = self.vm.program.len();
+        self.blocks.push((origin, self.vm.ro_data.len()));
+        self.vm.program.append(&mut bytes);
+        self.vm.ro_data.extend_from_slice(assembler.ro_data());
+        for (name, address) in assembler.symbols().code_labels() {
+            self.labels.insert(name.to_string(), address);
+        }
+        Ok(origin)
+    }
+
+    /// Assembles a block typed between `.block` and `.end` and runs it until it ends, or a
+    /// breakpoint or watchpoint stops it.
+    fn run_block(&mut self, source: &str) -> io::Result<()> {
+        if let Err(e) = self.assemble(source) {
+            self.fail(format_args!("Unable to parse block: {}", e))?;
+            return Ok(());
+        }
+        self.run_appended()
+    }
+
+    /// Appends hexadecimal bytecode to the program and runs it like a block, if it only
+    /// holds complete instructions using registers that exist.
+    fn run_hex(&mut self, hex: &str) -> io::Result<()> {
+        let mut bytes = match Self::parse_hex(hex) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.fail(format_args!("Unable to parse hex: {}", e))?;
+                return Ok(());
+            }
+        };
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.check_bytecode(&bytes) {
+            self.fail(format_args!("Invalid bytecode: {}", e))?;
+            return Ok(());
+        }
+        self.blocks.push((self.vm.program.len(), self.vm.ro_data.len()));
+        self.vm.program.append(&mut bytes);
+        self.run_appended()
+    }
+
+    /// Checks `bytes` decode as whole instructions whose registers are in range.
+    fn check_bytecode(&self, bytes: &[u8]) -> Result<(), String> {
+        let natives = self.vm.native_names();
+        let mut pc = 0;
+        while pc < bytes.len() {
+            let instruction = disassemble(bytes, pc, &natives)
+                .ok_or_else(|| format!("the instruction at byte {} is incomplete", pc))?;
+            for operand in &instruction.operands {
+                match operand {
+                    DecodedOperand::Register(reg) | DecodedOperand::FloatRegister(reg) if *reg >= 32 => {
+                        return Err(format!("register {} doesn't exist in `{}`", reg, instruction));
                     }
+                    _ => {}
                 }
-                ".program" => {
-                    println!("Listing instructions currently in VM's program vector:");
-                    self.vm.display_program_as_hex();
-                    println!("End of Program Listing");
+            }
+            pc += instruction.size;
+        }
+        Ok(())
+    }
+
+    /// Runs the code just appended to the program until it ends, or a breakpoint or
+    /// watchpoint stops it.
+    fn run_appended(&mut self) -> io::Result<()> {
+        match self.vm.run_until_breakpoint() {
+            StopReason::Halted(HaltReason::EndOfProgram) => Ok(()),
+            reason => self.report_stop(reason),
+        }
+    }
+
+    /// The prompt shown before reading a line.
+    fn prompt(&self) -> &'static str {
+        if self.pending_block.is_some() {
+            ".. "
+        } else if self.hex_mode {
+            "!> "
+        } else {
+            ">> "
+        }
+    }
+
+    /// Removes the last block of code appended to the program. What it did when it ran
+    /// isn't undone.
+    fn undo(&mut self) -> io::Result<()> {
+        let (origin, ro_origin) = match self.blocks.pop() {
+            Some(block) => block,
+            None => {
+                self.fail(format_args!("Nothing to undo"))?;
+                return Ok(());
+            }
+        };
+        let removed = self.vm.program.len() - origin;
+        self.vm.truncate_program(origin, ro_origin);
+        self.labels.retain(|_, address| *address < origin);
+        writeln!(self.output, "Removed {} bytes from address {}", removed, origin)?;
+        Ok(())
+    }
+
+    fn load(&mut self, path: &str) -> io::Result<()> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                self.fail(format_args!("Unable to read {}: {}", path, e))?;
+                return Ok(());
+            }
+        };
+        match self.assemble(&source) {
+            Ok(origin) => writeln!(
+                self.output,
+                "Loaded {} bytes at address {}",
+                self.vm.program.len() - origin,
+                origin
+            )?,
+            Err(e) => {
+                self.fail(format_args!("Unable to parse {}: {}", path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the program to `path`, as source when it has the `.iasm` extension and as the
+    /// raw bytecode otherwise.
+    fn save_program(&mut self, path: &str) -> io::Result<()> {
+        let is_source = Path::new(path).extension().is_some_and(|ext| ext == "iasm");
+        let contents = if is_source {
+            match disassemble_program(&self.vm.program, &self.vm.ro_data, &self.vm.native_names()) {
+                Ok(source) => source.into_bytes(),
+                Err(address) => {
+                    self.fail(format_args!("Unable to disassemble the instruction at {}", address))?;
+                    return Ok(());
                 }
-                ".registers" => {
-                    println!("Listing registers and all contents:");
-                    self.vm.display_registers_square();
-                    println!("End of Register Listing")
+            }
+        } else {
+            self.vm.program.clone()
+        };
+        match fs::write(path, contents) {
+            Ok(()) if !is_source && !self.vm.ro_data.is_empty() => writeln!(
+                self.output,
+                "Program saved to {}, without its read-only data: save it as .iasm to keep it",
+                path
+            )?,
+            Ok(()) => writeln!(self.output, "Program saved to {}", path)?,
+            Err(e) => self.fail(format_args!("Unable to write {}: {}", path, e))?,
+        }
+        Ok(())
+    }
+
+    /// Parses a breakpoint location, either an address or a label with or without its `@`.
+    fn parse_location(&self, location: &str) -> Option<usize> {
+        location
+            .parse()
+            .ok()
+            .or_else(|| self.labels.get(location.trim_start_matches('@')).copied())
+    }
+
+    fn set_breakpoint(&mut self, argument: &str) -> io::Result<()> {
+        if argument.is_empty() {
+            writeln!(self.output, "Breakpoints:")?;
+            for address in self.vm.breakpoints() {
+                writeln!(self.output, "{}", self.describe_address(address))?;
+            }
+            return Ok(());
+        }
+        match self.parse_location(argument) {
+            Some(address) => {
+                self.vm.add_breakpoint(address);
+                writeln!(self.output, "Breakpoint set at {}", self.describe_address(address))?;
+            }
+            None => self.fail(format_args!("Unknown address or label `{}`", argument))?,
+        }
+        Ok(())
+    }
+
+    fn delete_breakpoint(&mut self, argument: &str) -> io::Result<()> {
+        if argument.is_empty() {
+            self.vm.clear_breakpoints();
+            writeln!(self.output, "All breakpoints deleted")?;
+            return Ok(());
+        }
+        match self.parse_location(argument) {
+            Some(address) if self.vm.remove_breakpoint(address) => {
+                writeln!(self.output, "Breakpoint at {} deleted", self.describe_address(address))?
+            }
+            Some(address) => self.fail(format_args!("No breakpoint at {}", address))?,
+            None => self.fail(format_args!("Unknown address or label `{}`", argument))?,
+        }
+        Ok(())
+    }
+
+    fn watch(&mut self, argument: &str) -> io::Result<()> {
+        if argument.is_empty() {
+            writeln!(self.output, "Watchpoints:")?;
+            for (id, watchpoint) in self.vm.watchpoints() {
+                writeln!(self.output, "{}: {}", id, watchpoint)?;
+            }
+            return Ok(());
+        }
+        match argument.parse::<Watchpoint>() {
+            Ok(watchpoint) => {
+                let description = watchpoint.to_string();
+                let id = self.vm.add_watchpoint(watchpoint);
+                writeln!(self.output, "Watchpoint {} set on {}", id, description)?;
+            }
+            Err(e) => self.fail(format_args!("{}", e))?,
+        }
+        Ok(())
+    }
+
+    fn unwatch(&mut self, argument: &str) -> io::Result<()> {
+        if argument.is_empty() {
+            self.vm.clear_watchpoints();
+            writeln!(self.output, "All watchpoints deleted")?;
+            return Ok(());
+        }
+        match argument.parse() {
+            Ok(id) if self.vm.remove_watchpoint(id) => writeln!(self.output, "Watchpoint {} deleted", id)?,
+            _ => self.fail(format_args!("No watchpoint `{}`", argument))?,
+        }
+        Ok(())
+    }
+
+    fn report_stop(&mut self, reason: StopReason) -> io::Result<()> {
+        if let StopReason::Halted(halt) = &reason {
+            self.note_halt(halt);
+        }
+        match reason {
+            StopReason::Stepped => {}
+            StopReason::Breakpoint(address) => {
+                writeln!(self.output, "Breakpoint reached at {}", self.describe_address(address))?
+            }
+            StopReason::Watchpoint(hit) => writeln!(
+                self.output,
+                "Watchpoint {} triggered by {}: {}",
+                hit.id,
+                self.describe_address(hit.pc),
+                hit.event
+            )?,
+            StopReason::Halted(reason) => writeln!(self.output, "Program stopped: {:?}", reason)?,
+            StopReason::StartOfHistory => writeln!(self.output, "Reached the start of the recorded history")?,
+        }
+        self.show_where()?;
+        Ok(())
+    }
+
+    /// Reports why the line couldn't be executed, counting it as failed for `run_script`.
+    fn fail(&mut self, message: fmt::Arguments) -> io::Result<()> {
+        self.failures += 1;
+        writeln!(self.output, "{}", message)
+    }
+
+    /// Counts the line that made the program stop as failed if the VM faulted.
+    fn note_halt(&mut self, reason: &HaltReason) {
+        if let HaltReason::Illegal | HaltReason::Error(_) = reason {
+            self.failures += 1;
+        }
+    }
+
+    /// Prints the pc and the instruction it points at, disassembled.
+    fn show_where(&mut self) -> io::Result<()> {
+        let pc = self.vm.pc();
+        match disassemble(&self.vm.program, pc, &self.vm.native_names()) {
+            Some(instruction) => writeln!(self.output, "{}: {}", self.describe_address(pc), instruction)?,
+            None => writeln!(self.output, "{}: end of program", self.describe_address(pc))?,
+        }
+        Ok(())
+    }
+
+    /// Prints a summary of the VM's state: sizes rather than contents for the program, the heap
+    /// and the undo history, which `.program`, `.heap` and `.reverse-step` work with.
+    fn inspect(&mut self) -> io::Result<()> {
+        let natives = self.vm.native_names();
+        writeln!(self.output, "Inspecting the VM")?;
+        writeln!(self.output, "pc: {}", self.describe_address(self.vm.pc()))?;
+        writeln!(self.output, "equal_flag: {}", self.vm.equal_flag())?;
+        writeln!(self.output, "remainder: {}", self.vm.remainder())?;
+        writeln!(self.output, "program: {} bytes", self.vm.program.len())?;
+        writeln!(self.output, "read-only data: {} bytes", self.vm.ro_data.len())?;
+        writeln!(self.output, "heap: {} bytes", self.vm.heap().len())?;
+        writeln!(self.output, "history: {} instructions", self.vm.history_len())?;
+        writeln!(self.output, "breakpoints: {}", self.vm.breakpoints().count())?;
+        writeln!(self.output, "watchpoints: {}", self.vm.watchpoints().count())?;
+        if natives.is_empty() {
+            writeln!(self.output, "natives: none")?;
+        } else {
+            writeln!(self.output, "natives: {}", natives.join(", "))?;
+        }
+        writeln!(self.output, "Registers:")?;
+        self.vm.display_registers_square()?;
+        writeln!(self.output)?;
+        writeln!(self.output, "Float registers:")?;
+        self.vm.display_float_registers_square()?;
+        writeln!(self.output)
+    }
+
+    /// Formats `address` with the labels declared on it.
+    fn describe_address(&self, address: usize) -> String {
+        let mut names: Vec<&str> = self
+            .labels
+            .iter()
+            .filter(|(_, label)| **label == address)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        if names.is_empty() {
+            format!("{:04}", address)
+        } else {
+            format!("{:04} <{}>", address, names.join(", "))
+        }
+    }
+
+    fn show_heap(&mut self, argument: &str) -> io::Result<()> {
+        let heap = self.vm.heap();
+        let numbers: Result<Vec<usize>, _> = argument.split_whitespace().map(str::parse).collect();
+        let (start, len) = match numbers.as_deref() {
+            Ok([]) => (0, heap.len()),
+            Ok([start]) => (*start, heap.len().saturating_sub(*start)),
+            Ok([start, len]) => (*start, *len),
+            _ => {
+                self.fail(format_args!("Usage: .heap [<address> [<length>]]"))?;
+                return Ok(());
+            }
+        };
+        if heap.is_empty() {
+            writeln!(self.output, "The heap is empty")?;
+            return Ok(());
+        }
+        if start >= heap.len() {
+            self.fail(format_args!("Address {} is past the end of the {} byte heap", start, heap.len()))?;
+            return Ok(());
+        }
+        let end = start.saturating_add(len).min(heap.len());
+        let mut dump = String::new();
+        for (i, line) in heap[start..end].chunks(16).enumerate() {
+            dump.push_str(&hexdump_line(start + i * 16, line));
+            dump.push('\n');
+        }
+        write!(self.output, "{}", dump)
+    }
+
+    fn set_register(&mut self, argument: &str) -> io::Result<()> {
+        let (register, value) = match argument.split_once(' ') {
+            Some((register, value)) => (register, value.trim()),
+            None => {
+                self.fail(format_args!("Usage: .set $<register> <value>"))?;
+                return Ok(());
+            }
+        };
+        let register = match register.strip_prefix('$').map(str::parse::<usize>) {
+            Some(Ok(register)) if register < 32 => register,
+            _ => {
+                self.fail(format_args!("Expected a register from $0 to $31, got `{}`", register))?;
+                return Ok(());
+            }
+        };
+        if value.contains('.') {
+            match value.parse() {
+                Ok(value) => {
+                    self.vm.poke_float_register(register, value);
+                    writeln!(self.output, "${} set to {:?}", register, value)?;
                 }
-                ".help" => {
-                    println!("Vanadium REPL Help");
-                    println!(".help - Show this help message");
-                    println!(".quit - Quit the REPL");
-                    println!(".history - Show command history");
-                    println!(".program - Show the program in the VM");
-                    println!(".registers - Show the contents of the registers");
-                    println!(".inspect - Show the VM state");
-                    println!(".help_instruction - Show the Vanadium instruction set");
+                Err(_) => self.fail(format_args!("Expected a number, got `{}`", value))?,
+            }
+        } else {
+            match value.parse() {
+                Ok(value) => {
+                    self.vm.poke_register(register, value);
+                    writeln!(self.output, "${} set to {}", register, value)?;
                 }
-                ".inspect" => {
-                    println!("Inspecting the VM");
-                    println!("{:?}", self.vm);
+                Err(_) => self.fail(format_args!("Expected a number, got `{}`", value))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// How an instruction is written, its mnemonic in capitals followed by its operands.
+    fn syntax(opcode: Opcode) -> String {
+        let mut syntax = opcode.mnemonic().to_uppercase();
+        for operand in opcode.operands() {
+            syntax.push(' ');
+            syntax.push_str(operand.syntax());
+        }
+        syntax
+    }
+
+    /// Shows what an instruction does, how it is encoded, the other ways to write it and an
+    /// example.
+    fn help_instruction(&mut self, mnemonic: &str) -> io::Result<()> {
+        let opcode = Opcode::from(CompleteStr(&mnemonic.to_lowercase()));
+        if opcode == Opcode::IGL {
+            self.fail(format_args!("Unknown instruction `{}`, .help_instruction lists them", mnemonic))?;
+            return Ok(());
+        }
+        writeln!(self.output, "{}", Self::syntax(opcode))?;
+        writeln!(self.output, "{}", opcode.description())?;
+        let mut encoding = format!("opcode {} (1 byte)", opcode as u8);
+        for operand in opcode.operands() {
+            encoding.push_str(", ");
+            encoding.push_str(operand.encoding());
+        }
+        writeln!(self.output, "Encoding: {}", encoding)?;
+        if let Some(immediate) = opcode.immediate_form() {
+            writeln!(self.output, "With #<value> as its second operand, assembles to {}", immediate.mnemonic())?;
+        }
+        if let Some(register) = opcode.register_form() {
+            writeln!(self.output, "With a third register, assembles to {}", register.mnemonic())?;
+        }
+        if let Some((relative, absolute)) = opcode.branch_forms() {
+            writeln!(
+                self.output,
+                "With @<label> in place of the register, assembles to {} or {}",
+                relative.mnemonic(),
+                absolute.mnemonic()
+            )?;
+        }
+        writeln!(self.output, "Example:")?;
+        for line in opcode.example().lines() {
+            writeln!(self.output, "    {}", line)?;
+        }
+        // CALLN's example only assembles once its native is registered
+        if let Ok(bytes) = Assembler::for_vm(&self.vm).assemble(opcode.example()) {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(self.output, "    => {}", hex.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Parses the optional repeat count of `.step`, `.rstep` and `.rback`.
+    fn parse_count(argument: &str) -> Option<usize> {
+        match argument {
+            "" => Some(1),
+            count => count.parse().ok(),
+        }
+    }
+
+    fn restore(&mut self, path: &str) -> io::Result<()> {
+        let image = match fs::read(path) {
+            Ok(image) => image,
+            Err(e) => {
+                self.fail(format_args!("Unable to read {}: {}", path, e))?;
+                return Ok(());
+            }
+        };
+        match self.vm.restore(&image) {
+            Ok(()) => {
+                // The labels were declared for the program that was replaced
+                self.labels.clear();
+                self.blocks.clear();
+                writeln!(self.output, "VM state restored from {}", path)?;
+            }
+            Err(e) => self.fail(format_args!("Unable to restore {}: {}", path, e))?,
+        }
+        Ok(())
+    }
+
+    fn trace(&mut self, argument: &str) -> io::Result<()> {
+        match argument {
+            "" => self.fail(format_args!("Usage: .trace <file> or .trace off"))?,
+            "off" => {
+                if self.vm.stop_trace() {
+                    writeln!(self.output, "Trace stopped")?;
+                } else {
+                    self.fail(format_args!("No trace is being recorded"))?;
                 }
-                ".help_instruction" => {
-                    println!("Vanadium Instruction Set");
-                    println!("LOAD <register> <value> - Load a value into a register");
-                    println!("ADD <register1> <register2> <register3> - Add the values in register2 and register3 and store the result in register1");
-                    println!("SUB <register1> <register2> <register3> - Subtract the values in register2 and register3 and store the result in register1");
-                    println!("MUL <register1> <register2> <register3> - Multiply the values in register2 and register3 and store the result in register1");
-                    println!("DIV <register1> <register2> <register3> - Divide the values in register2 and register3 and store the result in register1");
-                    println!("HLT - Halt the program");
-                    println!("JMP <value> - Jump to a specific location in the program");
-                    println!("JMPF <value> - Jump forward a specific number of instructions");
-                    println!("JMPB <value> - Jump backward a specific number of instructions");
-                    println!("EQ <register1> <register2> <register3> - Compare the values in register2 and register3 and store 1 in register1 if they are equal, otherwise store 0");
-                    println!("NEQ <register1> <register2> <register3> - Compare the values in register2 and register3 and store 1 in register1 if they are not equal, otherwise store 0");
-                    println!("GT <register1> <register2> <register3> - Compare the values in register2 and register3 and store 1 in register1 if register2 is greater than register3, otherwise store 0");
-                    println!("LT <register1> <register2> <register3> - Compare the values in register2 and register3 and store 1 in register1 if register2 is less than register3, otherwise store 0");
-                    println!("GTE <register1> <register2> <register3> - Compare the values in register2 and register3 and store 1 in register1 if register2 is greater than or equal to register3, otherwise store 0");
-                    println!("LTE <register1> <register2> <register3> - Compare the values in register2 and register3 and store 1 in register1 if register2 is less than or equal to register3, otherwise store 0");
-                    println!("NOP - Do nothing");
-                    println!("End of Instruction Set");
+            }
+            path => match self.vm.start_trace(path) {
+                Ok(()) => writeln!(self.output, "Tracing to {}", path)?,
+                Err(e) => self.fail(format_args!("Unable to start trace: {}", e))?,
+            },
+        }
+        Ok(())
+    }
+
+    /// Moves the loaded replay `argument` steps forwards or backwards, printing each
+    /// instruction and the registers it changed.
+    fn replay_steps(&mut self, argument: &str, forwards: bool) -> io::Result<()> {
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => {
+                self.fail(format_args!("No trace loaded, use .replay <file>"))?;
+                return Ok(());
+            }
+        };
+        let count = match Self::parse_count(argument) {
+            Some(count) => count,
+            None => {
+                self.fail(format_args!("Expected a number of steps, got `{}`", argument))?;
+                return Ok(());
+            }
+        };
+        for _ in 0..count {
+            let step = if forwards {
+                replay.step_forward()
+            } else {
+                replay.step_backward()
+            };
+            let step = match step {
+                Some(step) => step,
+                None => {
+                    writeln!(self.output, "{} of the trace", if forwards { "End" } else { "Start" })?;
+                    break;
                 }
-                _ => {
-                    let parsed_program = match program(buffer.into()) {
-                            Ok((_, program)) => program,
-                            Err(_) => {
-                            println!("Unable to parse input");
-                            continue;
-                        }
-                    };
-
-                    println!("Parsed program: {:?}", parsed_program);
-                    
-                    self.vm.program.append(&mut parsed_program.to_bytes());
-                    self.vm.run_once();
+            };
+            writeln!(self.output, "{:04}: {}", step.pc, step.instruction)?;
+            for change in &step.changes {
+                match change {
+                    RegisterChange::Integer { register, old, new } => {
+                        writeln!(self.output, "    ${}: {} -> {}", register, old, new)?
+                    }
+                    RegisterChange::Float { register, old, new } => {
+                        writeln!(self.output, "    ${}: {:?} -> {:?}", register, old, new)?
+                    }
                 }
             }
+            if let Some(reason) = &step.halt {
+                writeln!(self.output, "    halted: {}", reason)?;
+            }
         }
+        writeln!(self.output, "At step {} of {}", replay.position(), replay.len())?;
+        Ok(())
     }
 
     /// Accepts a hexadecimal string WITHOUT a leading `0x` and returns a Vec of u8
-    /// Example for a LOAD command: 00 01 03 E8
-    #[allow(dead_code)]
-    fn parse_hex(&mut self, i: &str) -> Result<Vec<u8>, ParseIntError> {
-        let split = i.split(" ").collect::<Vec<&str>>();
+    /// Example for a LOAD command: 01 00 03 E8
+    fn parse_hex(i: &str) -> Result<Vec<u8>, ParseIntError> {
+        let split = i.split_whitespace().collect::<Vec<&str>>();
         let mut results: Vec<u8> = vec![];
         for hex_string in split {
             let byte = u8::from_str_radix(hex_string, 16);
@@ -132,3 +932,196 @@ impl REPL {
         Ok(results)
     }
 }
+
+/// Formats up to 16 bytes starting at `address` as hexadecimal then ASCII, unprintable
+/// bytes shown as `.`.
+fn hexdump_line(address: usize, bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for byte in bytes {
+        hex.push_str(&format!("{:02X} ", byte));
+    }
+    let ascii: String = bytes
+        .iter()
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+        .collect();
+    format!("{:04}: {:<48} |{}|", address, hex, ascii)
+}
+
+/// Where the history of interactive sessions is kept, in the home directory.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+fn readline_error(error: ReadlineError) -> io::Error {
+    match error {
+        ReadlineError::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::output::SharedBuffer;
+    use std::path::PathBuf;
+
+    fn run_script(script: &str) -> REPL {
+        let mut repl = REPL::with_output(Box::new(SharedBuffer::new()));
+        for line in script.lines() {
+            repl.execute_line(line.trim()).unwrap();
+        }
+        repl
+    }
+
+    /// Runs every `testdata/*.vrepl` session and compares what it printed with the `.out`
+    /// file next to it.
+    #[test]
+    fn test_golden_sessions() {
+        let testdata = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/repl/testdata");
+        let mut sessions = 0;
+        for entry in fs::read_dir(&testdata).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "vrepl") {
+                continue;
+            }
+            let input = fs::read(&path).unwrap();
+            let output = SharedBuffer::new();
+            let mut repl = REPL::with_output(Box::new(output.clone()));
+            repl.run(io::Cursor::new(input)).unwrap();
+            let expected = fs::read_to_string(path.with_extension("out")).unwrap();
+            assert_eq!(output.contents(), expected, "session {}", path.display());
+            sessions += 1;
+        }
+        assert!(sessions > 0);
+    }
+
+    #[test]
+    fn test_help_lists_every_completed_command() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_output(Box::new(output.clone()));
+        repl.execute_line(".help").unwrap();
+        let help = output.contents();
+        for command in completion::COMMANDS {
+            let listed = help
+                .lines()
+                .any(|line| line.split([' ', '[']).next() == Some(command));
+            assert!(listed, "{} isn't in the help", command);
+        }
+    }
+
+    #[test]
+    fn test_script_echoes_lines() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_output(Box::new(output.clone()));
+        let script = "load $0 #2\n.block\nloop: subi $0 #1 $0\neqi $0 #0\njneq @loop\n.end\n.pc\n";
+        assert_eq!(repl.run_script(script.as_bytes()).unwrap(), Vec::<usize>::new());
+        assert_eq!(
+            output.contents(),
+            ">> load $0 #2\n>> .block\nEnter the block, then .end to assemble and run it\n\
+             .. loop: subi $0 #1 $0\n.. eqi $0 #0\n.. jneq @loop\n.. .end\n>> .pc\npc: 0016\n"
+        );
+    }
+
+    #[test]
+    fn test_script_reports_failed_lines() {
+        let mut repl = REPL::with_output(Box::new(SharedBuffer::new()));
+        let script = "load $0\nload $1 #1\nsyscall #99\n!01 00\n.block\nhlt";
+        assert_eq!(repl.run_script(script.as_bytes()).unwrap(), vec![1, 3, 4, 5]);
+
+        let mut repl = REPL::with_output(Box::new(SharedBuffer::new()));
+        let script = "igl\n.quit\nload $0";
+        assert_eq!(repl.run_script(script.as_bytes()).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_quit_returns() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_output(Box::new(output.clone()));
+        repl.run(&b"load $0 #1\n.quit\nload $1 #2\n"[..]).unwrap();
+        assert_eq!(repl.vm.registers[0], 1);
+        assert_eq!(repl.vm.registers[1], 0);
+        assert!(output.contents().ends_with(">> Goodbye! We hope you had fun!\n"));
+    }
+
+    #[test]
+    fn test_programs_read_lines_from_the_repl_input() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_output(Box::new(output.clone()));
+        repl.run(&b"syscall #1\nhello\nload $1 #2\n"[..]).unwrap();
+        assert_eq!(repl.vm.read_heap_string(repl.vm.registers[0] as usize), b"hello");
+        assert_eq!(repl.vm.registers[1], 2);
+    }
+
+    #[test]
+    fn test_script_skips_blank_lines_and_comments() {
+        let mut repl = REPL::with_output(Box::new(SharedBuffer::new()));
+        let script = "load $0 #1\n\n   \n# set up the counter\n.block\n\n# inside a block\nload $1 #2\n.end\n";
+        assert_eq!(repl.run_script(script.as_bytes()).unwrap(), Vec::<usize>::new());
+        assert_eq!(repl.vm.registers[0], 1);
+        assert_eq!(repl.vm.registers[1], 2);
+        assert_eq!(repl.command_buffer, vec!["load $0 #1", ".block", "load $1 #2", ".end"]);
+    }
+
+    #[test]
+    fn test_end_of_input_returns() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_output(Box::new(output.clone()));
+        repl.run(&b"load $0 #1"[..]).unwrap();
+        assert_eq!(repl.vm.registers[0], 1);
+        assert!(output.contents().ends_with(">> \n"));
+    }
+
+    #[test]
+    fn test_clear_program() {
+        let mut repl = run_script("top: load $0 #7\nmsg: .asciiz 'hi'\n.clear_program");
+        assert!(repl.vm.program.is_empty());
+        assert!(repl.vm.ro_data.is_empty());
+        assert_eq!(repl.vm.pc(), 0);
+        assert!(repl.labels.is_empty());
+        assert_eq!(repl.vm.registers[0], 7);
+
+        repl.execute_line("load $1 #2").unwrap();
+        assert_eq!(repl.vm.program, vec![1, 1, 0, 2]);
+        assert_eq!(repl.vm.registers[1], 2);
+    }
+
+    #[test]
+    fn test_clear_registers() {
+        let repl = run_script("load $0 #7\nloadf $1 #1.5\n.clear_registers");
+        assert_eq!(repl.vm.registers, [0; 32]);
+        assert_eq!(repl.vm.float_registers, [0.0; 32]);
+        assert_eq!(repl.vm.pc(), 14);
+    }
+
+    #[test]
+    fn test_reset() {
+        let repl = run_script("load $0 #7\nload $1 #2\ndiv $0 $1 $2\neq $0 $0\naloc $0\n.reset");
+        assert_eq!(repl.vm.pc(), 0);
+        assert!(repl.vm.heap().is_empty());
+        assert!(!repl.vm.equal_flag());
+        assert_eq!(repl.vm.remainder(), 0);
+        assert_eq!(repl.vm.registers[2], 3);
+        assert_eq!(repl.vm.program.len(), 17);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut repl = run_script("load $0 #7\nend: hlt\nmsg: .asciiz 'hi'");
+        assert_eq!(repl.vm.program.len(), 5);
+        repl.execute_line(".undo").unwrap();
+        assert_eq!(repl.vm.program.len(), 5);
+        assert!(repl.vm.ro_data.is_empty());
+        assert!(repl.labels.contains_key("end"));
+        repl.execute_line(".undo").unwrap();
+        assert_eq!(repl.vm.program, vec![1, 0, 0, 7]);
+        assert_eq!(repl.vm.pc(), 4);
+        assert!(!repl.labels.contains_key("end"));
+        repl.execute_line(".undo").unwrap();
+        repl.execute_line(".undo").unwrap();
+        assert!(repl.vm.program.is_empty());
+        assert_eq!(repl.vm.pc(), 0);
+        assert_eq!(repl.vm.registers[0], 7);
+    }
+}
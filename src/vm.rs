@@ -1,396 +0,0 @@
-use crate::instruction::Opcode;
-
-#[derive(Debug)]
-pub struct VM {
-    pub registers: [i32; 32],
-    pc: usize,
-    pub program: Vec<u8>,
-    heap: Vec<u8>,
-    remainder: u32,
-    equal_flag: bool,
-}
-
-impl Default for VM {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl VM {
-    pub fn new() -> VM {
-        VM {
-            registers: [0; 32],
-            pc: 0,
-            program: vec![],
-            heap: vec![],
-            remainder: 0,
-            equal_flag: false,
-        }
-    }
-
-    pub fn run(&mut self) {
-        let mut is_running = true;
-        while is_running {
-            is_running = self.execute_instruction();
-        }
-    }
-
-    pub fn run_once(&mut self) {
-        self.execute_instruction();
-    }
-
-    fn execute_instruction(&mut self) -> bool {
-        if self.pc >= self.program.len() {
-            return true;
-        }
-
-        match self.decode_opcode() {
-            Opcode::HLT => {
-                println!("HLT encountered");
-                return true;
-            }
-            Opcode::IGL => {
-                println!("IGL encountered");
-                return true;
-            }
-            Opcode::LOAD => {
-                let register = self.next_8_bits() as usize;
-                let number = self.next_16_bits();
-                self.registers[register] = number as i32;
-            }
-            Opcode::ADD => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.registers[self.next_8_bits() as usize] = register1 + register2;
-            }
-            Opcode::SUB => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.registers[self.next_8_bits() as usize] = register1 - register2;
-            }
-            Opcode::MUL => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.registers[self.next_8_bits() as usize] = register1 * register2;
-            }
-            Opcode::DIV => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.registers[self.next_8_bits() as usize] = register1 / register2;
-                self.remainder = (register1 % register2) as u32;
-            }
-            Opcode::JMP => {
-                let target = self.registers[self.next_8_bits() as usize] as usize;
-                self.pc = target;
-            }
-            Opcode::JMPF => {
-                let target = self.registers[self.next_8_bits() as usize] as usize;
-                self.pc += target;
-            }
-            Opcode::JMPB => {
-                let target = self.registers[self.next_8_bits() as usize] as usize;
-                self.pc -= target;
-            }
-            Opcode::EQ => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.equal_flag = register1 == register2;
-                // self.next_8_bits();
-            }
-            Opcode::NEQ => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.equal_flag = register1 != register2;
-                // self.next_8_bits();
-            }
-            Opcode::GT => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.equal_flag = register1 > register2;
-                // self.next_8_bits();
-            }
-            Opcode::LT => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.equal_flag = register1 < register2;
-                // self.next_8_bits();
-            }
-            Opcode::GTQ => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.equal_flag = register1 >= register2;
-                // self.next_8_bits();
-            }
-            Opcode::LTQ => {
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.equal_flag = register1 <= register2;
-                // self.next_8_bits();
-            }
-            Opcode::JEQ => {
-                let target = self.registers[self.next_8_bits() as usize] as usize;
-                if self.equal_flag {
-                    self.pc = target;
-                    return true;
-                }
-                // self.next_16_bits();
-            }
-            Opcode::JNEQ => {
-                let target = self.registers[self.next_8_bits() as usize] as usize;
-                if !self.equal_flag {
-                    self.pc = target;
-                    return true;
-                }
-                // self.next_16_bits();
-            }
-            Opcode::ALOC => {
-                let size = self.registers[self.next_8_bits() as usize] as usize;
-                let new_len = self.heap.len() + size;
-                self.heap.resize(new_len, 0);
-            }
-            _ => {
-                println!("Unrecognized opcode found! Terminating!");
-                return false;
-            }
-        }
-        false
-    }
-
-    fn next_8_bits(&mut self) -> u8 {
-        let result = self.program[self.pc];
-        self.pc += 1;
-        result
-    }
-
-    fn next_16_bits(&mut self) -> u16 {
-        let result = ((self.program[self.pc] as u16) << 8) | self.program[self.pc + 1] as u16;
-        self.pc += 2;
-        result
-    }
-
-    fn decode_opcode(&mut self) -> Opcode {
-        let opcode = Opcode::from(self.program[self.pc]);
-        self.pc += 1;
-        opcode
-    }
-
-    pub fn add_byte(&mut self, byte: u8) {
-        self.program.push(byte);
-    }
-
-    pub fn display_registers_square(&self) {
-        let side_width = (self.registers.len() as f64).sqrt().ceil() as usize;
-        for (i, register) in self.registers.iter().enumerate() {
-            print!("{:03}: {:10} ", i, register);
-            if (i + 1) % side_width == 0 {
-                println!();
-            }
-        }
-    }
-
-    pub fn display_program_as_hex(&self) {
-        for (i, byte) in self.program.iter().enumerate() {
-            print!("{:02X} ", byte);
-            if (i + 1) % 4 == 0 {
-                println!();
-            }
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::vec;
-
-    use super::*;
-
-    fn get_test_vm() -> VM {
-        let vm = VM::new();
-        vm
-    }
-
-    #[test]
-    fn test_vm_new() {
-        let vm = VM::new();
-        assert_eq!(vm.registers[0], 0);
-    }
-
-    #[test]
-    fn test_opcode_hlt() {
-        let mut test_vm = VM::new();
-        let test_bytes = vec![0, 0, 0, 0];
-        test_vm.program = test_bytes;
-        test_vm.run();
-        assert_eq!(test_vm.pc, 1);
-    }
-
-    #[test]
-    fn test_opcode_igl() {
-        let mut test_vm = VM::new();
-        let test_bytes = vec![200, 0, 0, 0];
-        test_vm.program = test_bytes;
-        test_vm.run();
-        assert_eq!(test_vm.pc, 1);
-    }
-
-    #[test]
-    fn test_load_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.program = vec![1, 0, 1, 244]; // Remember, this is how we represent 500 using two u8s in little endian format
-        test_vm.run_once();
-        assert_eq!(test_vm.registers[0], 500);
-    }
-
-    #[test]
-    fn test_jmp_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 1;
-        test_vm.program = vec![6, 0, 0, 0];
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 1);
-    }
-
-    #[test]
-    fn test_jmpf_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 2;
-        test_vm.program = vec![7, 0, 0, 0, 6, 0, 0, 0];
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 4);
-    }
-
-    #[test]
-    fn test_jmpb_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 2;
-        test_vm.pc = 4;
-        test_vm.program = vec![8, 0, 0, 0, 6, 0, 0, 0];
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 2);
-    }
-
-    #[test]
-    fn test_eq_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 10;
-        test_vm.registers[1] = 10;
-        test_vm.program = vec![9, 0, 1, 9, 0, 1];
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 20;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, false);
-    }
-
-    #[test]
-    fn test_neq_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 10;
-        test_vm.registers[1] = 10;
-        test_vm.program = vec![10, 0, 1, 10, 0, 1];
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, false);
-        test_vm.registers[1] = 20;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-    }
-
-    #[test]
-    fn test_gt_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 20;
-        test_vm.registers[1] = 10;
-        test_vm.program = vec![11, 0, 1, 11, 0, 1];
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 30;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, false);
-    }
-
-    #[test]
-    fn test_lt_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 10;
-        test_vm.registers[1] = 20;
-        test_vm.program = vec![12, 0, 1, 12, 0, 1];
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 5;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, false);
-    }
-
-    #[test]
-    fn test_gtq_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 20;
-        test_vm.registers[1] = 10;
-        test_vm.program = vec![13, 0, 1, 13, 0, 1, 13, 0, 1];
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 20;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 30;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, false);
-    }
-
-    #[test]
-    fn test_ltq_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 10;
-        test_vm.registers[1] = 20;
-        test_vm.program = vec![14, 0, 1, 0, 14, 0, 1, 0, 14, 0, 1, 0];
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 10;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, true);
-        test_vm.registers[1] = 5;
-        test_vm.run_once();
-        assert_eq!(test_vm.equal_flag, false);
-    }
-
-    #[test]
-    fn test_jeq_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 4;
-        test_vm.registers[1] = 2;
-        test_vm.registers[2] = 0;
-        test_vm.equal_flag = true;
-        test_vm.program = vec![15, 0, 6, 1, 6, 2];
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 4);
-        test_vm.run_once();
-        test_vm.equal_flag = false;
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 2);
-    }
-
-    #[test]
-    fn test_jneq_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 2;
-        test_vm.registers[1] = 4;
-        test_vm.registers[2] = 0;
-        test_vm.equal_flag = false;
-        test_vm.program = vec![16, 0, 6, 1, 6, 2];
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 2);
-        test_vm.run_once();
-        test_vm.equal_flag = true;
-        test_vm.run_once();
-        assert_eq!(test_vm.pc, 0);
-    }
-
-    #[test]
-    fn test_aloc_opcode() {
-        let mut test_vm = get_test_vm();
-        test_vm.registers[0] = 1024;
-        test_vm.program = vec![17, 0, 0, 0];
-        test_vm.run_once();
-        assert_eq!(test_vm.heap.len(), 1024);
-    }   
-}
@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+
+use super::VM;
+
+/// The state of the VM between two instructions.
+///
+/// The heap only ever grows by appending, so its length is enough to bring it back.
+#[derive(Debug, PartialEq, Clone)]
+pub(super) struct Checkpoint {
+    pub registers: [i32; 32],
+    pub float_registers: [f64; 32],
+    pub pc: usize,
+    pub heap_len: usize,
+    pub equal_flag: bool,
+    pub remainder: i32,
+}
+
+impl Checkpoint {
+    fn of(vm: &VM) -> Checkpoint {
+        Checkpoint {
+            registers: vm.registers,
+            float_registers: vm.float_registers,
+            pc: vm.pc,
+            heap_len: vm.heap.len(),
+            equal_flag: vm.equal_flag,
+            remainder: vm.remainder,
+        }
+    }
+}
+
+/// What one instruction changed, holding the values from before it ran.
+#[derive(Debug, PartialEq, Clone)]
+pub(super) struct Delta {
+    pub pc: usize,
+    pub heap_len: usize,
+    pub equal_flag: bool,
+    pub remainder: i32,
+    /// Registers in the order the instruction wrote them, with their previous value.
+    pub registers: Vec<(usize, i32)>,
+    pub float_registers: Vec<(usize, f64)>,
+}
+
+/// A checkpoint and the instructions executed since.
+#[derive(Debug)]
+pub(super) struct Segment {
+    pub checkpoint: Checkpoint,
+    pub deltas: Vec<Delta>,
+}
+
+/// The instructions a VM executed, recorded so they can be undone. A checkpoint of the whole
+/// state is taken every `checkpoint_interval` instructions and only what changed is recorded
+/// in between. Once more than `capacity` instructions are recorded, the oldest checkpoints
+/// are dropped with the instructions following them.
+#[derive(Debug)]
+pub(super) struct History {
+    checkpoint_interval: usize,
+    capacity: usize,
+    segments: VecDeque<Segment>,
+    len: usize,
+    recording: Option<Delta>,
+}
+
+impl History {
+    pub fn new(checkpoint_interval: usize, capacity: usize) -> History {
+        History {
+            checkpoint_interval: checkpoint_interval.max(1),
+            capacity,
+            segments: VecDeque::new(),
+            len: 0,
+            recording: None,
+        }
+    }
+
+    /// Forgets every instruction recorded so far.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.len = 0;
+        self.recording = None;
+    }
+
+    /// Number of instructions that can be undone.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Starts recording the instruction `vm` is about to execute.
+    pub fn begin(&mut self, vm: &VM) {
+        let full = match self.segments.back() {
+            Some(segment) => segment.deltas.len() >= self.checkpoint_interval,
+            None => true,
+        };
+        if full {
+            self.segments.push_back(Segment {
+                checkpoint: Checkpoint::of(vm),
+                deltas: vec![],
+            });
+        }
+        self.recording = Some(Delta {
+            pc: vm.pc,
+            heap_len: vm.heap.len(),
+            equal_flag: vm.equal_flag,
+            remainder: vm.remainder,
+            registers: vec![],
+            float_registers: vec![],
+        });
+    }
+
+    pub fn register_written(&mut self, register: usize, old: i32) {
+        if let Some(delta) = self.recording.as_mut() {
+            delta.registers.push((register, old));
+        }
+    }
+
+    pub fn float_register_written(&mut self, register: usize, old: f64) {
+        if let Some(delta) = self.recording.as_mut() {
+            delta.float_registers.push((register, old));
+        }
+    }
+
+    /// Stores the instruction recorded since `begin`.
+    pub fn end(&mut self) {
+        let delta = match self.recording.take() {
+            Some(delta) => delta,
+            None => return,
+        };
+        if let Some(segment) = self.segments.back_mut() {
+            segment.deltas.push(delta);
+            self.len += 1;
+        }
+        while self.len > self.capacity && self.segments.len() > 1 {
+            if let Some(segment) = self.segments.pop_front() {
+                self.len -= segment.deltas.len();
+            }
+        }
+    }
+
+    /// Removes the last instruction recorded.
+    pub fn pop(&mut self) -> Option<Delta> {
+        let segment = self.segments.back_mut()?;
+        let delta = segment.deltas.pop();
+        if segment.deltas.is_empty() {
+            self.segments.pop_back();
+        }
+        if delta.is_some() {
+            self.len -= 1;
+        }
+        delta
+    }
+
+    /// The last segment, if none of its instructions starts at an address `stop` accepts.
+    pub fn last_segment_unless<F: Fn(usize) -> bool>(&self, stop: F) -> Option<&Segment> {
+        self.segments
+            .back()
+            .filter(|segment| !segment.deltas.iter().any(|delta| stop(delta.pc)))
+    }
+
+    pub fn pop_segment(&mut self) -> Option<Segment> {
+        let segment = self.segments.pop_back()?;
+        self.len -= segment.deltas.len();
+        Some(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_takes_checkpoints() {
+        let mut vm = VM::new();
+        let mut history = History::new(2, 10);
+        for pc in 0..5 {
+            vm.pc = pc;
+            history.begin(&vm);
+            history.register_written(1, pc as i32);
+            history.end();
+        }
+        assert_eq!(history.len(), 5);
+        assert_eq!(history.segments.len(), 3);
+        assert_eq!(history.segments[1].checkpoint.pc, 2);
+
+        let delta = history.pop().unwrap();
+        assert_eq!(delta.pc, 4);
+        assert_eq!(delta.registers, vec![(1, 4)]);
+        assert_eq!(history.segments.len(), 2);
+        assert_eq!(history.pop_segment().unwrap().deltas.len(), 2);
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn test_history_drops_oldest_checkpoints() {
+        let vm = VM::new();
+        let mut history = History::new(2, 3);
+        for _ in 0..6 {
+            history.begin(&vm);
+            history.end();
+        }
+        assert_eq!(history.len(), 2);
+        while history.pop().is_some() {}
+        assert_eq!(history.len(), 0);
+        assert!(history.pop().is_none());
+    }
+}
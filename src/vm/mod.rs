@@ -0,0 +1,1954 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::instruction::Opcode;
+
+use self::history::History;
+use self::observer::VmObserver;
+use self::output::OutputSink;
+use self::syscall::{DefaultSyscalls, SyscallHandler, SyscallOutcome};
+use self::trace::TraceRecorder;
+use self::watchpoint::{WatchEvent, WatchHit, Watchpoint};
+
+mod history;
+pub mod observer;
+pub mod output;
+pub mod snapshot;
+pub mod syscall;
+pub mod trace;
+pub mod watchpoint;
+
+/// A Rust function scripts can call with `calln @name`. It receives the values of the
+/// argument registers, and what it returns is stored in `$0`.
+pub type NativeFn = fn(&mut VM, &[i32]) -> Result<i32, VmError>;
+
+/// Errors that stop the VM in the middle of a program.
+#[derive(Debug, PartialEq, Clone)]
+pub enum VmError {
+    UnknownSyscall(u16),
+    NoSyscallHandler,
+    UnknownNative(u16),
+    Native(String),
+    Io(String),
+    /// DIV or MOD with a divisor of zero.
+    DivisionByZero,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::UnknownSyscall(number) => write!(f, "Unknown syscall {}", number),
+            VmError::NoSyscallHandler => write!(f, "No syscall handler installed"),
+            VmError::UnknownNative(index) => write!(f, "No native function with index {}", index),
+            VmError::Native(message) => write!(f, "Native function failed: {}", message),
+            VmError::Io(e) => write!(f, "I/O error: {}", e),
+            VmError::DivisionByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+impl From<io::Error> for VmError {
+    fn from(e: io::Error) -> Self {
+        VmError::Io(e.to_string())
+    }
+}
+
+/// Why the VM stopped running a program.
+#[derive(Debug, PartialEq, Clone)]
+pub enum HaltReason {
+    /// A HLT instruction was executed.
+    Hlt,
+    /// An opcode the VM doesn't know was found.
+    Illegal,
+    /// The program counter ran past the end of the program.
+    EndOfProgram,
+    /// The program exited through `SYS_EXIT` with this code.
+    Exit(i32),
+    Error(VmError),
+}
+
+/// Why `step` or `run_until_breakpoint` handed control back.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StopReason {
+    /// The requested number of instructions ran.
+    Stepped,
+    /// The instruction at this address has a breakpoint and hasn't run yet.
+    Breakpoint(usize),
+    /// The instruction that just ran triggered a watchpoint.
+    Watchpoint(WatchHit),
+    /// The program stopped.
+    Halted(HaltReason),
+    /// Stepping backwards reached the oldest instruction the history kept.
+    StartOfHistory,
+}
+
+impl From<VmError> for HaltReason {
+    fn from(e: VmError) -> Self {
+        HaltReason::Error(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct VM {
+    pub registers: [i32; 32],
+    pub float_registers: [f64; 32],
+    pc: usize,
+    pub program: Vec<u8>,
+    pub ro_data: Vec<u8>,
+    heap: Vec<u8>,
+    remainder: i32,
+    equal_flag: bool,
+    syscall_handler: Option<Box<dyn SyscallHandler>>,
+    natives: Vec<(String, NativeFn)>,
+    exit_code: Option<i32>,
+    output: OutputSink,
+    observer: Option<Box<dyn VmObserver>>,
+    /// Whether the observer is the recorder installed by `start_trace`.
+    tracing: bool,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_watchpoint: usize,
+    /// Watchpoints triggered by the instruction being executed.
+    watch_hits: Vec<(usize, WatchEvent)>,
+    history: Option<History>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VM {
+    pub fn new() -> VM {
+        VM {
+            registers: [0; 32],
+            float_registers: [0.0; 32],
+            pc: 0,
+            program: vec![],
+            ro_data: vec![],
+            heap: vec![],
+            remainder: 0,
+            equal_flag: false,
+            syscall_handler: Some(Box::new(DefaultSyscalls::new())),
+            natives: vec![],
+            exit_code: None,
+            output: OutputSink::default(),
+            observer: None,
+            tracing: false,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![],
+            next_watchpoint: 0,
+            watch_hits: vec![],
+            history: None,
+        }
+    }
+
+    /// Redirects everything the VM prints, program output and diagnostics alike, to `output`.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = OutputSink::new(output);
+    }
+
+    /// The sink the VM prints to, for syscall handlers and natives producing output.
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+
+    /// Installs an observer notified of everything the VM does, or removes it with `None`.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn VmObserver>>) {
+        self.observer = observer;
+        self.tracing = false;
+    }
+
+    /// Removes the installed observer and hands it back.
+    pub fn take_observer(&mut self) -> Option<Box<dyn VmObserver>> {
+        self.tracing = false;
+        self.observer.take()
+    }
+
+    /// Records every instruction executed from now on to the trace file at `path`, to be
+    /// stepped through with [`trace::Replay`]. The recorder is the VM's observer, so it
+    /// replaces any observer already installed.
+    pub fn start_trace<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let recorder = TraceRecorder::new(Box::new(File::create(path)?), self)?;
+        self.set_observer(Some(Box::new(recorder)));
+        self.tracing = true;
+        Ok(())
+    }
+
+    /// Stops recording the trace started by `start_trace`, flushing it to its file. Returns
+    /// `false`, leaving the observer in place, if no trace is being recorded.
+    pub fn stop_trace(&mut self) -> bool {
+        if !self.tracing {
+            return false;
+        }
+        self.set_observer(None);
+        true
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn heap(&self) -> &[u8] {
+        &self.heap
+    }
+
+    pub fn equal_flag(&self) -> bool {
+        self.equal_flag
+    }
+
+    pub fn remainder(&self) -> i32 {
+        self.remainder
+    }
+
+    /// Replaces the handler servicing `SYSCALL`. `None` makes every syscall fail.
+    pub fn set_syscall_handler(&mut self, handler: Option<Box<dyn SyscallHandler>>) {
+        self.syscall_handler = handler;
+    }
+
+    /// Makes `native` callable from programs assembled afterwards as `calln @name`.
+    /// Registering a name again replaces the previous function, keeping its index so
+    /// already assembled programs call the new one.
+    pub fn register_native(&mut self, name: &str, native: NativeFn) {
+        match self.natives.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = native,
+            None => self.natives.push((name.to_string(), native)),
+        }
+    }
+
+    /// Names of the registered natives, in index order.
+    pub fn native_names(&self) -> Vec<String> {
+        self.natives.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Exit code given to the last `SYS_EXIT` syscall, if the program exited that way.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Makes `run_until_breakpoint` stop before the instruction at `address` runs. Returns
+    /// `false` if there already was a breakpoint there.
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Returns `false` if there was no breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Addresses with a breakpoint, in increasing order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Makes `step` and `run_until_breakpoint` stop after an instruction triggers
+    /// `watchpoint`, and returns the id to remove it with.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    /// Returns `false` if there is no watchpoint with that id.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|(watched, _)| *watched != id);
+        self.watchpoints.len() != count
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// The watchpoints with their ids, in the order they were added.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints.iter().map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    /// Records the instructions executed from now on so `reverse_step` and
+    /// `reverse_continue` can undo them. The whole state is saved every
+    /// `checkpoint_interval` instructions and only what changed in between; at most about
+    /// `capacity` instructions are kept. Output and syscall side effects can't be undone.
+    pub fn enable_history(&mut self, checkpoint_interval: usize, capacity: usize) {
+        self.history = Some(History::new(checkpoint_interval, capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Number of instructions that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Undoes up to `count` instructions, stopping early when the history runs out.
+    pub fn reverse_step(&mut self, count: usize) -> StopReason {
+        for _ in 0..count {
+            match self.history.as_mut().and_then(History::pop) {
+                Some(delta) => self.undo(delta),
+                None => return StopReason::StartOfHistory,
+            }
+        }
+        StopReason::Stepped
+    }
+
+    /// Undoes instructions until the pc is back on a breakpoint or the history runs out.
+    /// At least one instruction is undone, so calling this again goes further back.
+    pub fn reverse_continue(&mut self) -> StopReason {
+        loop {
+            let history = match self.history.as_mut() {
+                Some(history) => history,
+                None => return StopReason::StartOfHistory,
+            };
+            let breakpoints = &self.breakpoints;
+            // Segments without a breakpoint are skipped by restoring their checkpoint
+            if history
+                .last_segment_unless(|pc| breakpoints.contains(&pc))
+                .is_some()
+            {
+                if let Some(segment) = history.pop_segment() {
+                    self.restore_checkpoint(segment.checkpoint);
+                }
+                continue;
+            }
+            match history.pop() {
+                Some(delta) => self.undo(delta),
+                None => return StopReason::StartOfHistory,
+            }
+            if self.breakpoints.contains(&self.pc) {
+                return StopReason::Breakpoint(self.pc);
+            }
+        }
+    }
+
+    fn undo(&mut self, delta: history::Delta) {
+        for (register, old) in delta.registers.into_iter().rev() {
+            self.registers[register] = old;
+        }
+        for (register, old) in delta.float_registers.into_iter().rev() {
+            self.float_registers[register] = old;
+        }
+        self.pc = delta.pc;
+        self.heap.truncate(delta.heap_len);
+        self.equal_flag = delta.equal_flag;
+        self.remainder = delta.remainder;
+    }
+
+    fn restore_checkpoint(&mut self, checkpoint: history::Checkpoint) {
+        self.registers = checkpoint.registers;
+        self.float_registers = checkpoint.float_registers;
+        self.pc = checkpoint.pc;
+        self.heap.truncate(checkpoint.heap_len);
+        self.equal_flag = checkpoint.equal_flag;
+        self.remainder = checkpoint.remainder;
+    }
+
+    /// Removes the program and its read-only data, and moves the pc back to the start.
+    pub fn clear_program(&mut self) {
+        self.program.clear();
+        self.ro_data.clear();
+        self.pc = 0;
+        self.forget_history();
+    }
+
+    /// Removes the end of the program from `len` bytes on, with the read-only data from
+    /// `ro_len` on. A pc past the new end moves back to it.
+    pub fn truncate_program(&mut self, len: usize, ro_len: usize) {
+        self.program.truncate(len);
+        self.ro_data.truncate(ro_len);
+        self.pc = self.pc.min(self.program.len());
+        self.forget_history();
+    }
+
+    /// Sets every integer and float register to zero.
+    pub fn clear_registers(&mut self) {
+        self.registers = [0; 32];
+        self.float_registers = [0.0; 32];
+        self.forget_history();
+    }
+
+    /// Sets an integer register from outside the program.
+    pub fn poke_register(&mut self, register: usize, value: i32) {
+        self.registers[register] = value;
+        self.forget_history();
+    }
+
+    /// Sets a float register from outside the program.
+    pub fn poke_float_register(&mut self, register: usize, value: f64) {
+        self.float_registers[register] = value;
+        self.forget_history();
+    }
+
+    /// Moves the pc back to the start and empties the heap, the flags and the remainder,
+    /// leaving the program and registers alone.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.heap.clear();
+        self.equal_flag = false;
+        self.remainder = 0;
+        self.exit_code = None;
+        self.forget_history();
+    }
+
+    /// Changing the state outside of an instruction makes the recorded history meaningless.
+    fn forget_history(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+    }
+
+    /// Executes instructions until the program stops, returning why it did.
+    pub fn run(&mut self) -> HaltReason {
+        loop {
+            if let Err(reason) = self.execute_instruction() {
+                return reason;
+            }
+        }
+    }
+
+    pub fn run_once(&mut self) {
+        let _ = self.execute_instruction();
+    }
+
+    /// Executes up to `count` instructions, ignoring breakpoints, stopping early if the
+    /// program does or a watchpoint triggers.
+    pub fn step(&mut self, count: usize) -> StopReason {
+        for _ in 0..count {
+            if let Some(reason) = self.execute_watched() {
+                return reason;
+            }
+        }
+        StopReason::Stepped
+    }
+
+    /// Runs until the program stops, reaches a breakpoint or triggers a watchpoint. The
+    /// instruction at the current pc always runs, so calling this again continues past the
+    /// breakpoint it stopped at.
+    pub fn run_until_breakpoint(&mut self) -> StopReason {
+        loop {
+            if let Some(reason) = self.execute_watched() {
+                return reason;
+            }
+            if self.breakpoints.contains(&self.pc) {
+                return StopReason::Breakpoint(self.pc);
+            }
+        }
+    }
+
+    /// Executes one instruction, returning why the VM has to stop after it if it does.
+    fn execute_watched(&mut self) -> Option<StopReason> {
+        let pc = self.pc;
+        if let Err(reason) = self.execute_instruction() {
+            return Some(StopReason::Halted(reason));
+        }
+        let (id, event) = self.watch_hits.drain(..).next()?;
+        Some(StopReason::Watchpoint(WatchHit { id, pc, event }))
+    }
+
+    fn execute_instruction(&mut self) -> Result<(), HaltReason> {
+        self.watch_hits.clear();
+        if self.pc >= self.program.len() {
+            self.halt(&HaltReason::EndOfProgram);
+            return Err(HaltReason::EndOfProgram);
+        }
+
+        let start = self.pc;
+        let opcode = Opcode::from(self.program[start]);
+        if let Some(mut observer) = self.observer.take() {
+            observer.before_instruction(self, start, opcode);
+            self.observer = Some(observer);
+        }
+        if let Some(mut history) = self.history.take() {
+            history.begin(self);
+            self.history = Some(history);
+        }
+        let result = self.execute_opcode(start);
+        if let Some(history) = self.history.as_mut() {
+            history.end();
+        }
+        if let Some(mut observer) = self.observer.take() {
+            observer.after_instruction(self, start, opcode);
+            self.observer = Some(observer);
+        }
+        if let Err(reason) = &result {
+            self.halt(reason);
+        }
+        result
+    }
+
+    /// Executes the instruction at `start`, returning why the VM has to stop if it does.
+    fn execute_opcode(&mut self, start: usize) -> Result<(), HaltReason> {
+        match self.decode_opcode() {
+            Opcode::HLT => return Err(HaltReason::Hlt),
+            Opcode::IGL => return Err(HaltReason::Illegal),
+            Opcode::LOAD => {
+                let register = self.next_8_bits() as usize;
+                let number = self.next_16_bits();
+                self.set_register(register, number as i32);
+            }
+            Opcode::ADD => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 + register2);
+            }
+            Opcode::SUB => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 - register2);
+            }
+            Opcode::MUL => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 * register2);
+            }
+            Opcode::DIV => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                let destination = self.next_8_bits() as usize;
+                if register2 == 0 {
+                    return Err(VmError::DivisionByZero.into());
+                }
+                self.set_register(destination, register1.wrapping_div(register2));
+                self.remainder = register1.wrapping_rem(register2);
+            }
+            Opcode::JMP => {
+                let target = self.registers[self.next_8_bits() as usize] as usize;
+                self.jump(start, target);
+            }
+            Opcode::JMPF => {
+                let target = self.registers[self.next_8_bits() as usize] as usize;
+                self.jump(start, self.pc + target);
+            }
+            Opcode::JMPB => {
+                let target = self.registers[self.next_8_bits() as usize] as usize;
+                self.jump(start, self.pc - target);
+            }
+            Opcode::EQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 == register2;
+            }
+            Opcode::NEQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 != register2;
+            }
+            Opcode::GT => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 > register2;
+            }
+            Opcode::LT => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 < register2;
+            }
+            Opcode::GTQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 >= register2;
+            }
+            Opcode::LTQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 <= register2;
+            }
+            Opcode::JEQ => {
+                let target = self.registers[self.next_8_bits() as usize] as usize;
+                if self.equal_flag {
+                    self.jump(start, target);
+                }
+            }
+            Opcode::JNEQ => {
+                let target = self.registers[self.next_8_bits() as usize] as usize;
+                if !self.equal_flag {
+                    self.jump(start, target);
+                }
+            }
+            Opcode::ALOC => {
+                let size = self.registers[self.next_8_bits() as usize] as usize;
+                self.grow_heap(&vec![0; size]);
+            }
+            Opcode::AND => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 & register2);
+            }
+            Opcode::OR => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 | register2);
+            }
+            Opcode::XOR => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 ^ register2);
+            }
+            Opcode::NOT => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(!register1);
+            }
+            // Shift amounts only use their low 5 bits, so shifting by 32 or more wraps
+            // around instead of panicking.
+            Opcode::SHL => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1.wrapping_shl(register2 as u32));
+            }
+            Opcode::SHR => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 as u32).wrapping_shr(register2 as u32) as i32);
+            }
+            Opcode::SAR => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1.wrapping_shr(register2 as u32));
+            }
+            Opcode::MOVREM => {
+                self.set_next_register(self.remainder);
+            }
+            // DIV truncates towards zero, so the remainder it leaves behind takes the sign of
+            // the dividend. MOD is the Euclidean modulo instead: the result is always in
+            // `0..divisor.abs()`, whatever the signs of the operands.
+            Opcode::MOD => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                let destination = self.next_8_bits() as usize;
+                if register2 == 0 {
+                    return Err(VmError::DivisionByZero.into());
+                }
+                self.set_register(destination, register1.wrapping_rem_euclid(register2));
+            }
+            Opcode::MOV => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register(register1);
+            }
+            Opcode::ADDI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let immediate = self.next_immediate();
+                self.set_next_register(register1.wrapping_add(immediate));
+            }
+            Opcode::SUBI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let immediate = self.next_immediate();
+                self.set_next_register(register1.wrapping_sub(immediate));
+            }
+            Opcode::MULI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let immediate = self.next_immediate();
+                self.set_next_register(register1.wrapping_mul(immediate));
+            }
+            Opcode::EQI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 == self.next_immediate();
+            }
+            Opcode::NEQI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 != self.next_immediate();
+            }
+            Opcode::GTI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 > self.next_immediate();
+            }
+            Opcode::LTI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 < self.next_immediate();
+            }
+            Opcode::GTQI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 >= self.next_immediate();
+            }
+            Opcode::LTQI => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 <= self.next_immediate();
+            }
+            Opcode::SEQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 == register2) as i32);
+            }
+            Opcode::SNEQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 != register2) as i32);
+            }
+            Opcode::SGT => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 > register2) as i32);
+            }
+            Opcode::SLT => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 < register2) as i32);
+            }
+            Opcode::SGTQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 >= register2) as i32);
+            }
+            Opcode::SLTQ => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                let register2 = self.registers[self.next_8_bits() as usize];
+                self.set_next_register((register1 <= register2) as i32);
+            }
+            Opcode::JMPI => {
+                let target = self.next_16_bits() as usize;
+                self.jump(start, target);
+            }
+            Opcode::JEQI => {
+                let target = self.next_16_bits() as usize;
+                if self.equal_flag {
+                    self.jump(start, target);
+                }
+            }
+            Opcode::JNEQI => {
+                let target = self.next_16_bits() as usize;
+                if !self.equal_flag {
+                    self.jump(start, target);
+                }
+            }
+            Opcode::JMPR => {
+                let offset = self.next_immediate();
+                self.jump(start, self.relative_target(offset));
+            }
+            Opcode::JEQR => {
+                let offset = self.next_immediate();
+                if self.equal_flag {
+                    self.jump(start, self.relative_target(offset));
+                }
+            }
+            Opcode::JNEQR => {
+                let offset = self.next_immediate();
+                if !self.equal_flag {
+                    self.jump(start, self.relative_target(offset));
+                }
+            }
+            Opcode::LOADF => {
+                let register = self.next_8_bits() as usize;
+                let number = self.next_f64();
+                self.set_float_register(register, number);
+            }
+            Opcode::ADDF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.set_next_float_register(register1 + register2);
+            }
+            Opcode::SUBF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.set_next_float_register(register1 - register2);
+            }
+            Opcode::MULF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.set_next_float_register(register1 * register2);
+            }
+            Opcode::DIVF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.set_next_float_register(register1 / register2);
+            }
+            Opcode::EQF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 == register2;
+            }
+            Opcode::NEQF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 != register2;
+            }
+            Opcode::GTF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 > register2;
+            }
+            Opcode::LTF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 < register2;
+            }
+            Opcode::GTQF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 >= register2;
+            }
+            Opcode::LTQF => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                let register2 = self.float_registers[self.next_8_bits() as usize];
+                self.equal_flag = register1 <= register2;
+            }
+            Opcode::ITOF => {
+                let register1 = self.registers[self.next_8_bits() as usize];
+                self.set_next_float_register(register1 as f64);
+            }
+            // Truncates towards zero, saturating at the bounds of i32 (NaN becomes 0)
+            Opcode::FTOI => {
+                let register1 = self.float_registers[self.next_8_bits() as usize];
+                self.set_next_register(register1 as i32);
+            }
+            Opcode::PRTS => {
+                let offset = self.next_16_bits() as usize;
+                let string = VM::read_string(&self.ro_data, offset).to_vec();
+                self.write_output(&string)?;
+            }
+            Opcode::PRTH => {
+                let address = self.registers[self.next_8_bits() as usize] as usize;
+                let string = self.read_heap(address);
+                self.write_output(&string)?;
+            }
+            Opcode::LDSTR => {
+                let offset = self.next_16_bits() as usize;
+                let string = VM::read_string(&self.ro_data, offset).to_vec();
+                let address = self.alloc_string(&string);
+                self.set_next_register(address);
+            }
+            Opcode::STRCAT => {
+                let register1 = self.registers[self.next_8_bits() as usize] as usize;
+                let register2 = self.registers[self.next_8_bits() as usize] as usize;
+                let mut string = self.read_heap(register1);
+                string.extend(self.read_heap(register2));
+                let address = self.alloc_string(&string);
+                self.set_next_register(address);
+            }
+            Opcode::STRLEN => {
+                let register1 = self.registers[self.next_8_bits() as usize] as usize;
+                let length = self.read_heap(register1).len();
+                self.set_next_register(length as i32);
+            }
+            // Stores -1, 0 or 1 depending on how the strings compare byte by byte
+            Opcode::STRCMP => {
+                let register1 = self.registers[self.next_8_bits() as usize] as usize;
+                let register2 = self.registers[self.next_8_bits() as usize] as usize;
+                let ordering = self.read_heap(register1).cmp(&self.read_heap(register2));
+                self.set_next_register(ordering as i32);
+            }
+            Opcode::SYSCALL => {
+                let number = self.next_16_bits();
+                if let SyscallOutcome::Exit(code) = self.syscall(number)? {
+                    self.exit_code = Some(code);
+                    return Err(HaltReason::Exit(code));
+                }
+            }
+            Opcode::CALLN => {
+                let index = self.next_16_bits();
+                let argc = self.next_8_bits();
+                let args: Vec<i32> = (0..argc)
+                    .map(|_| self.registers[self.next_8_bits() as usize])
+                    .collect();
+                let value = self.call_native(index, &args)?;
+                self.set_register(0, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_register(&mut self, register: usize, value: i32) {
+        let old = self.registers[register];
+        self.registers[register] = value;
+        self.register_written(register, old, value);
+    }
+
+    /// Tells the observer and the watchpoints that `register` went from `old` to `new`.
+    fn register_written(&mut self, register: usize, old: i32, new: i32) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.register_write(register, old, new);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.register_written(register, old);
+        }
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.register_written(register, old, new) {
+                let event = WatchEvent::RegisterWrite { register, old, new };
+                self.watch_hits.push((*id, event));
+            }
+        }
+    }
+
+    /// Tells the observer and the watchpoints about `len` bytes of the heap accessed at
+    /// `address`.
+    fn heap_accessed(&mut self, address: usize, len: usize, write: bool) {
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.heap_accessed(address, len, write) {
+                let event = if write {
+                    WatchEvent::HeapWrite { address, len }
+                } else {
+                    WatchEvent::HeapRead { address, len }
+                };
+                self.watch_hits.push((*id, event));
+            }
+        }
+    }
+
+    /// Stores `value` in the register named by the next byte of the program.
+    fn set_next_register(&mut self, value: i32) {
+        let register = self.next_8_bits() as usize;
+        self.set_register(register, value);
+    }
+
+    fn set_float_register(&mut self, register: usize, value: f64) {
+        let old = self.float_registers[register];
+        self.float_registers[register] = value;
+        self.float_register_written(register, old, value);
+    }
+
+    fn float_register_written(&mut self, register: usize, old: f64, new: f64) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.float_register_write(register, old, new);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.float_register_written(register, old);
+        }
+    }
+
+    /// Stores `value` in the float register named by the next byte of the program.
+    fn set_next_float_register(&mut self, value: f64) {
+        let register = self.next_8_bits() as usize;
+        self.set_float_register(register, value);
+    }
+
+    fn jump(&mut self, from: usize, to: usize) {
+        self.pc = to;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.jump(from, to);
+        }
+    }
+
+    /// Relative jumps are measured from the end of the jump instruction, like JMPF and JMPB.
+    fn relative_target(&self, offset: i32) -> usize {
+        (self.pc as isize + offset as isize) as usize
+    }
+
+    /// Reads the heap string at `address`, including its terminator as far as the observer
+    /// is concerned.
+    fn read_heap(&mut self, address: usize) -> Vec<u8> {
+        let string = VM::read_string(&self.heap, address).to_vec();
+        let terminator = (address + string.len() < self.heap.len()) as usize;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.heap_read(address, string.len() + terminator);
+        }
+        self.heap_accessed(address, string.len() + terminator, false);
+        string
+    }
+
+    /// Appends `bytes` to the heap and returns the address they were written at.
+    fn grow_heap(&mut self, bytes: &[u8]) -> usize {
+        let address = self.heap.len();
+        self.heap.extend_from_slice(bytes);
+        if let Some(observer) = self.observer.as_mut() {
+            observer.heap_write(address, bytes);
+        }
+        self.heap_accessed(address, bytes.len(), true);
+        address
+    }
+
+    fn halt(&mut self, reason: &HaltReason) {
+        match reason {
+            HaltReason::Hlt => self.diagnostic(format_args!("HLT encountered")),
+            HaltReason::Illegal => self.diagnostic(format_args!("IGL encountered")),
+            HaltReason::Error(e) => self.diagnostic(format_args!("{}", e)),
+            HaltReason::EndOfProgram | HaltReason::Exit(_) => {}
+        }
+        if let Some(mut observer) = self.observer.take() {
+            observer.halt(self, reason);
+            self.observer = Some(observer);
+        }
+    }
+
+    /// Reports the registers a syscall handler or native changed behind the VM's back.
+    fn report_register_changes(&mut self, before: &[i32; 32], float_before: &[f64; 32]) {
+        for (register, old) in before.iter().enumerate() {
+            let new = self.registers[register];
+            if *old != new {
+                self.register_written(register, *old, new);
+            }
+        }
+        for (register, old) in float_before.iter().enumerate() {
+            let new = self.float_registers[register];
+            if old.to_bits() != new.to_bits() {
+                self.float_register_written(register, *old, new);
+            }
+        }
+    }
+
+    fn next_8_bits(&mut self) -> u8 {
+        let result = self.program[self.pc];
+        self.pc += 1;
+        result
+    }
+
+    fn next_16_bits(&mut self) -> u16 {
+        let result = ((self.program[self.pc] as u16) << 8) | self.program[self.pc + 1] as u16;
+        self.pc += 2;
+        result
+    }
+
+    /// Reads a 16 bit immediate and sign-extends it, so `#-1` encodes as `FF FF`.
+    fn next_immediate(&mut self) -> i32 {
+        self.next_16_bits() as i16 as i32
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.program[self.pc..self.pc + 8]);
+        self.pc += 8;
+        f64::from_be_bytes(bytes)
+    }
+
+    /// Writes program output, such as strings printed by PRTS, to the output sink.
+    pub fn write_output(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        self.output.write_all(bytes)?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Writes a line about the state of the VM to the output sink. A sink that can't be
+    /// written to has nowhere to report the failure, so it is ignored.
+    fn diagnostic(&mut self, message: fmt::Arguments) {
+        let _ = writeln!(self.output, "{}", message);
+    }
+
+    fn syscall(&mut self, number: u16) -> Result<SyscallOutcome, VmError> {
+        // The handler is taken out while it runs so it can be handed the VM mutably
+        let mut handler = self.syscall_handler.take().ok_or(VmError::NoSyscallHandler)?;
+        let (before, float_before) = (self.registers, self.float_registers);
+        let result = handler.syscall(number, self);
+        self.syscall_handler = Some(handler);
+        self.report_register_changes(&before, &float_before);
+        result
+    }
+
+    fn call_native(&mut self, index: u16, args: &[i32]) -> Result<i32, VmError> {
+        let native = match self.natives.get(index as usize) {
+            Some((_, native)) => *native,
+            None => return Err(VmError::UnknownNative(index)),
+        };
+        let (before, float_before) = (self.registers, self.float_registers);
+        let result = native(self, args);
+        self.report_register_changes(&before, &float_before);
+        result
+    }
+
+    /// Returns the NUL-terminated heap string starting at `address`, without its terminator.
+    pub fn read_heap_string(&self, address: usize) -> &[u8] {
+        VM::read_string(&self.heap, address)
+    }
+
+    /// Returns the NUL-terminated string starting at `address` in `memory`, without its
+    /// terminator. A string running off the end of `memory` stops there.
+    fn read_string(memory: &[u8], address: usize) -> &[u8] {
+        let bytes = memory.get(address..).unwrap_or(&[]);
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        &bytes[..end]
+    }
+
+    /// Copies `string` with a NUL terminator at the end of the heap, growing it, and returns
+    /// the address it was written at.
+    pub fn alloc_string(&mut self, string: &[u8]) -> i32 {
+        let mut bytes = string.to_vec();
+        bytes.push(0);
+        self.grow_heap(&bytes) as i32
+    }
+
+    fn decode_opcode(&mut self) -> Opcode {
+        let opcode = Opcode::from(self.program[self.pc]);
+        self.pc += 1;
+        opcode
+    }
+
+    pub fn add_byte(&mut self, byte: u8) {
+        self.program.push(byte);
+    }
+
+    pub fn display_registers_square(&mut self) -> io::Result<()> {
+        let side_width = (self.registers.len() as f64).sqrt().ceil() as usize;
+        for (i, register) in self.registers.iter().enumerate() {
+            write!(self.output, "{:03}: {:10} ", i, register)?;
+            if (i + 1) % side_width == 0 {
+                writeln!(self.output)?;
+            }
+        }
+        self.output.flush()
+    }
+
+    pub fn display_float_registers_square(&mut self) -> io::Result<()> {
+        let side_width = (self.float_registers.len() as f64).sqrt().ceil() as usize;
+        for (i, register) in self.float_registers.iter().enumerate() {
+            write!(self.output, "{:03}: {:10} ", i, register)?;
+            if (i + 1) % side_width == 0 {
+                writeln!(self.output)?;
+            }
+        }
+        self.output.flush()
+    }
+
+    pub fn display_program_as_hex(&mut self) -> io::Result<()> {
+        for (i, byte) in self.program.iter().enumerate() {
+            write!(self.output, "{:02X} ", byte)?;
+            if (i + 1) % 4 == 0 {
+                writeln!(self.output)?;
+            }
+        }
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::output::SharedBuffer;
+    use super::syscall::{RecordingSyscalls, SYS_CLOCK, SYS_EXIT};
+    use super::*;
+    use crate::assembler::Assembler;
+
+    #[allow(clippy::let_and_return)]
+    fn get_test_vm() -> VM {
+        let vm = VM::new();
+        vm
+    }
+
+    #[test]
+    fn test_vm_new() {
+        let vm = VM::new();
+        assert_eq!(vm.registers[0], 0);
+    }
+
+    #[test]
+    fn test_opcode_hlt() {
+        let mut test_vm = VM::new();
+        let test_bytes = vec![0, 0, 0, 0];
+        test_vm.program = test_bytes;
+        test_vm.run();
+        assert_eq!(test_vm.pc, 1);
+    }
+
+    #[test]
+    fn test_opcode_igl() {
+        let mut test_vm = VM::new();
+        let test_bytes = vec![200, 0, 0, 0];
+        test_vm.program = test_bytes;
+        test_vm.run();
+        assert_eq!(test_vm.pc, 1);
+    }
+
+    #[test]
+    fn test_load_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![1, 0, 1, 244]; // Remember, this is how we represent 500 using two u8s in little endian format
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[0], 500);
+    }
+
+    #[test]
+    fn test_div_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = -7;
+        test_vm.registers[1] = 2;
+        test_vm.program = vec![5, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], -3);
+        assert_eq!(test_vm.remainder, -1);
+    }
+
+    #[test]
+    fn test_div_by_zero_stops_the_vm() {
+        let mut test_vm = get_test_vm();
+        test_vm.set_output(Box::new(SharedBuffer::new()));
+        test_vm.registers[0] = 7;
+        test_vm.registers[2] = 9;
+        test_vm.program = vec![5, 0, 1, 2];
+        assert_eq!(test_vm.run(), HaltReason::Error(VmError::DivisionByZero));
+        assert_eq!(test_vm.registers[2], 9);
+        assert_eq!(test_vm.pc, 4);
+        test_vm.pc = 0;
+        test_vm.registers[0] = i32::MIN;
+        test_vm.registers[1] = -1;
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], i32::MIN);
+    }
+
+    #[test]
+    fn test_movrem_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 17;
+        test_vm.registers[1] = 5;
+        test_vm.program = vec![5, 0, 1, 2, 25, 3];
+        test_vm.run_once();
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 3);
+        assert_eq!(test_vm.registers[3], 2);
+    }
+
+    #[test]
+    fn test_mod_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = -7;
+        test_vm.registers[1] = 3;
+        test_vm.program = vec![26, 0, 1, 2, 26, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 2);
+        test_vm.registers[1] = -3;
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 2);
+    }
+
+    #[test]
+    fn test_mod_by_zero_stops_the_vm() {
+        let mut test_vm = get_test_vm();
+        test_vm.set_output(Box::new(SharedBuffer::new()));
+        test_vm.registers[0] = 7;
+        test_vm.program = vec![26, 0, 1, 2];
+        assert_eq!(test_vm.run(), HaltReason::Error(VmError::DivisionByZero));
+        assert_eq!(test_vm.registers[2], 0);
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_mov_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 42;
+        test_vm.program = vec![27, 0, 5];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[5], 42);
+        assert_eq!(test_vm.registers[0], 42);
+    }
+
+    #[test]
+    fn test_addi_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.program = vec![28, 0, 0, 5, 1, 28, 0, 0xFF, 0xFF, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], 15);
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], 9);
+        assert_eq!(test_vm.pc, 10);
+    }
+
+    #[test]
+    fn test_subi_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.program = vec![29, 0, 0, 3, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], 7);
+    }
+
+    #[test]
+    fn test_muli_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.program = vec![30, 0, 0xFF, 0xFE, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], -20);
+    }
+
+    #[test]
+    fn test_immediate_arithmetic_wraps() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = i32::MAX;
+        test_vm.registers[1] = i32::MIN;
+        test_vm.program = vec![
+            28, 0, 0, 1, 2, // addi $0 #1 $2
+            29, 1, 0, 1, 3, // subi $1 #1 $3
+            30, 0, 0x7F, 0xFF, 4, // muli $0 #32767 $4
+        ];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], i32::MIN);
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[3], i32::MAX);
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[4], i32::MAX.wrapping_mul(32767));
+    }
+
+    #[test]
+    fn test_immediate_comparison_opcodes() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 5;
+        test_vm.program = vec![
+            31, 0, 0, 5, // eqi $0 #5
+            32, 0, 0, 5, // neqi $0 #5
+            33, 0, 0, 4, // gti $0 #4
+            34, 0, 0, 4, // lti $0 #4
+            35, 0, 0, 5, // gtei $0 #5
+            36, 0, 0xFF, 0xFF, // ltei $0 #-1
+        ];
+        let expected = [true, false, true, false, true, false];
+        for flag in expected {
+            test_vm.run_once();
+            assert_eq!(test_vm.equal_flag, flag);
+        }
+    }
+
+    #[test]
+    fn test_register_comparison_opcodes() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 5;
+        test_vm.registers[1] = 7;
+        test_vm.program = vec![
+            37, 0, 1, 2, // seq $0 $1 $2
+            38, 0, 1, 2, // sneq $0 $1 $2
+            39, 0, 1, 2, // sgt $0 $1 $2
+            40, 0, 1, 2, // slt $0 $1 $2
+            41, 0, 0, 2, // sgte $0 $0 $2
+            42, 1, 0, 2, // slte $1 $0 $2
+        ];
+        let expected = [0, 1, 0, 1, 1, 0];
+        for value in expected {
+            test_vm.run_once();
+            assert_eq!(test_vm.registers[2], value);
+            assert!(!test_vm.equal_flag);
+        }
+    }
+
+    #[test]
+    fn test_jmp_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 1;
+        test_vm.program = vec![6, 0, 0, 0];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 1);
+    }
+
+    #[test]
+    fn test_jmpf_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 2;
+        test_vm.program = vec![7, 0, 0, 0, 6, 0, 0, 0];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_jmpb_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 2;
+        test_vm.pc = 4;
+        test_vm.program = vec![8, 0, 0, 0, 6, 0, 0, 0];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 2);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_eq_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![9, 0, 1, 9, 0, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 20;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, false);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_neq_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![10, 0, 1, 10, 0, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, false);
+        test_vm.registers[1] = 20;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_gt_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 20;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![11, 0, 1, 11, 0, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 30;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, false);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_lt_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.registers[1] = 20;
+        test_vm.program = vec![12, 0, 1, 12, 0, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 5;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, false);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_gtq_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 20;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![13, 0, 1, 13, 0, 1, 13, 0, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 20;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 30;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, false);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn test_ltq_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.registers[1] = 20;
+        test_vm.program = vec![14, 0, 1, 0, 14, 0, 1, 0, 14, 0, 1, 0];
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 10;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.registers[1] = 5;
+        test_vm.run_once();
+        assert_eq!(test_vm.equal_flag, false);
+    }
+
+    #[test]
+    fn test_jeq_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 4;
+        test_vm.registers[1] = 2;
+        test_vm.registers[2] = 0;
+        test_vm.equal_flag = true;
+        test_vm.program = vec![15, 0, 6, 1, 6, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 4);
+        test_vm.run_once();
+        test_vm.equal_flag = false;
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 2);
+    }
+
+    #[test]
+    fn test_jneq_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 2;
+        test_vm.registers[1] = 4;
+        test_vm.registers[2] = 0;
+        test_vm.equal_flag = false;
+        test_vm.program = vec![16, 0, 6, 1, 6, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 2);
+        test_vm.run_once();
+        test_vm.equal_flag = true;
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 0);
+    }
+
+    #[test]
+    fn test_jmpi_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![43, 0, 10];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 10);
+    }
+
+    #[test]
+    fn test_jeqi_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![44, 0, 10, 44, 0, 10];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 3);
+        test_vm.equal_flag = true;
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 10);
+    }
+
+    #[test]
+    fn test_jneqi_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![45, 0, 10];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 10);
+    }
+
+    #[test]
+    fn test_jmpr_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![46, 0, 2, 0, 0, 46, 0xFF, 0xF8];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 5);
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 0);
+    }
+
+    #[test]
+    fn test_jeqr_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![47, 0, 4, 47, 0xFF, 0xFD];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 3);
+        test_vm.equal_flag = true;
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 3);
+    }
+
+    #[test]
+    fn test_jneqr_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.equal_flag = true;
+        test_vm.program = vec![48, 0, 4, 48, 0, 4];
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 3);
+        test_vm.equal_flag = false;
+        test_vm.run_once();
+        assert_eq!(test_vm.pc, 10);
+    }
+
+    #[test]
+    fn test_label_loop() {
+        let mut test_vm = get_test_vm();
+        let mut assembler = Assembler::new();
+        test_vm.program = assembler
+            .assemble("load $0 #5\nloop: addi $1 #2 $1\nsubi $0 #1 $0\nneq $0 #0\njeq @loop\nhlt")
+            .unwrap();
+        test_vm.run();
+        assert_eq!(test_vm.registers[0], 0);
+        assert_eq!(test_vm.registers[1], 10);
+    }
+
+    #[test]
+    fn test_loadf_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![49, 3];
+        test_vm.program.extend(2.5f64.to_be_bytes());
+        test_vm.run_once();
+        assert_eq!(test_vm.float_registers[3], 2.5);
+        assert_eq!(test_vm.pc, 10);
+    }
+
+    #[test]
+    fn test_float_arithmetic_opcodes() {
+        let mut test_vm = get_test_vm();
+        test_vm.float_registers[0] = 1.5;
+        test_vm.float_registers[1] = 0.5;
+        test_vm.program = vec![50, 0, 1, 2, 51, 0, 1, 2, 52, 0, 1, 2, 53, 0, 1, 2];
+        let expected = [2.0, 1.0, 0.75, 3.0];
+        for value in expected {
+            test_vm.run_once();
+            assert_eq!(test_vm.float_registers[2], value);
+        }
+    }
+
+    #[test]
+    fn test_float_comparison_opcodes() {
+        let mut test_vm = get_test_vm();
+        test_vm.float_registers[0] = 1.5;
+        test_vm.float_registers[1] = 2.5;
+        test_vm.program = vec![54, 0, 1, 55, 0, 1, 56, 0, 1, 57, 0, 1, 58, 0, 0, 59, 1, 0];
+        let expected = [false, true, false, true, true, false];
+        for flag in expected {
+            test_vm.run_once();
+            assert_eq!(test_vm.equal_flag, flag);
+        }
+    }
+
+    #[test]
+    fn test_float_conversion_opcodes() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = -7;
+        test_vm.float_registers[1] = 3.99;
+        test_vm.program = vec![60, 0, 0, 61, 1, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.float_registers[0], -7.0);
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], 3);
+    }
+
+    #[test]
+    fn test_ldstr_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.ro_data = b"ab\0cd\0".to_vec();
+        test_vm.heap = vec![7];
+        test_vm.program = vec![64, 0, 3, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], 1);
+        assert_eq!(test_vm.heap, b"\x07cd\0");
+    }
+
+    #[test]
+    fn test_heap_string_opcodes() {
+        let mut test_vm = get_test_vm();
+        test_vm.heap = b"foo\0bar\0".to_vec();
+        test_vm.registers[0] = 0;
+        test_vm.registers[1] = 4;
+        test_vm.program = vec![
+            65, 0, 1, 2, // strcat $0 $1 $2
+            66, 2, 3, // strlen $2 $3
+            67, 0, 1, 4, // strcmp $0 $1 $4
+            67, 0, 0, 5, // strcmp $0 $0 $5
+        ];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 8);
+        assert_eq!(&test_vm.heap[8..], b"foobar\0");
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[3], 6);
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[4], 1);
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[5], 0);
+    }
+
+    #[test]
+    fn test_read_string_stops_at_end_of_memory() {
+        assert_eq!(VM::read_string(b"abc", 1), b"bc");
+        assert_eq!(VM::read_string(b"abc", 10), b"");
+    }
+
+    #[test]
+    fn test_syscall_opcode() {
+        let mut test_vm = get_test_vm();
+        let handler = RecordingSyscalls::new();
+        let calls = handler.calls();
+        test_vm.set_syscall_handler(Some(Box::new(handler)));
+        test_vm.registers[1] = 5;
+        test_vm.program = vec![68, 0, 2, 68, 0, 3, 0];
+        test_vm.run();
+        assert_eq!(test_vm.exit_code(), Some(5));
+        assert_eq!(test_vm.pc, 6);
+        let numbers: Vec<u16> = calls.borrow().iter().map(|call| call.number).collect();
+        assert_eq!(numbers, vec![SYS_CLOCK, SYS_EXIT]);
+    }
+
+    #[test]
+    fn test_syscall_without_handler() {
+        let mut test_vm = get_test_vm();
+        test_vm.set_syscall_handler(None);
+        test_vm.program = vec![68, 0, 0, 1, 0, 0, 1];
+        test_vm.run();
+        assert_eq!(test_vm.pc, 3);
+        assert_eq!(test_vm.registers[0], 0);
+    }
+
+    #[test]
+    fn test_calln_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.register_native("sum", |vm, args| {
+            vm.registers[9] = args.len() as i32;
+            Ok(args.iter().sum())
+        });
+        test_vm.registers[1] = 20;
+        test_vm.registers[2] = 22;
+        test_vm.program = Assembler::for_vm(&test_vm)
+            .assemble("calln @sum $1 $2\nhlt")
+            .unwrap();
+        test_vm.run();
+        assert_eq!(test_vm.registers[0], 42);
+        assert_eq!(test_vm.registers[9], 2);
+    }
+
+    #[test]
+    fn test_calln_error_stops_the_vm() {
+        let mut test_vm = get_test_vm();
+        let output = SharedBuffer::new();
+        test_vm.set_output(Box::new(output.clone()));
+        test_vm.register_native("fail", |_, _| Err(VmError::Native("nope".to_string())));
+        test_vm.program = vec![69, 0, 0, 0, 1, 0, 0, 1];
+        test_vm.run();
+        assert_eq!(test_vm.pc, 4);
+        assert!(output.contents().contains("nope"));
+        assert_eq!(test_vm.registers[0], 0);
+        assert_eq!(test_vm.call_native(1, &[]), Err(VmError::UnknownNative(1)));
+    }
+
+    #[test]
+    fn test_register_native_replaces_by_name() {
+        let mut test_vm = get_test_vm();
+        test_vm.register_native("a", |_, _| Ok(1));
+        test_vm.register_native("b", |_, _| Ok(2));
+        test_vm.register_native("a", |_, _| Ok(3));
+        assert_eq!(test_vm.native_names(), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(test_vm.call_native(0, &[]), Ok(3));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = Assembler::new().assemble("load $0 #1\nload $1 #2\nhlt").unwrap();
+        assert_eq!(test_vm.step(2), StopReason::Stepped);
+        assert_eq!(test_vm.registers[1], 2);
+        assert_eq!(test_vm.step(5), StopReason::Halted(HaltReason::Hlt));
+        assert_eq!(test_vm.pc, 9);
+        assert_eq!(test_vm.step(1), StopReason::Halted(HaltReason::EndOfProgram));
+    }
+
+    #[test]
+    fn test_run_until_breakpoint() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = Assembler::new()
+            .assemble("load $0 #3\nloop: subi $0 #1 $0\neq $0 #0\njneq @loop\nhlt")
+            .unwrap();
+        assert!(test_vm.add_breakpoint(4));
+        assert!(!test_vm.add_breakpoint(4));
+        assert_eq!(test_vm.run_until_breakpoint(), StopReason::Breakpoint(4));
+        assert_eq!(test_vm.registers[0], 3);
+        assert_eq!(test_vm.run_until_breakpoint(), StopReason::Breakpoint(4));
+        assert_eq!(test_vm.registers[0], 2);
+        assert!(test_vm.remove_breakpoint(4));
+        assert!(!test_vm.remove_breakpoint(4));
+        assert_eq!(test_vm.run_until_breakpoint(), StopReason::Halted(HaltReason::Hlt));
+        assert_eq!(test_vm.registers[0], 0);
+        assert_eq!(test_vm.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn test_register_watchpoints() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = Assembler::new()
+            .assemble("load $3 #90\nload $0 #1\nload $3 #90\naddi $3 #20 $3\nhlt")
+            .unwrap();
+        let changed = test_vm.add_watchpoint(Watchpoint::Register(3));
+        let above = test_vm.add_watchpoint("$3 > 100".parse().unwrap());
+        let event = WatchEvent::RegisterWrite { register: 3, old: 0, new: 90 };
+        let hit = WatchHit { id: changed, pc: 0, event };
+        assert_eq!(test_vm.run_until_breakpoint(), StopReason::Watchpoint(hit));
+
+        // Writing the value the register already holds doesn't count as a change
+        assert!(test_vm.remove_watchpoint(changed));
+        assert!(!test_vm.remove_watchpoint(changed));
+        let event = WatchEvent::RegisterWrite { register: 3, old: 90, new: 110 };
+        let hit = WatchHit { id: above, pc: 12, event };
+        assert_eq!(test_vm.step(10), StopReason::Watchpoint(hit));
+        assert_eq!(test_vm.watchpoints().count(), 1);
+    }
+
+    #[test]
+    fn test_heap_watchpoints() {
+        let mut test_vm = get_test_vm();
+        test_vm.ro_data = b"ab\0".to_vec();
+        test_vm.program = vec![64, 0, 0, 1, 65, 1, 1, 2, 0];
+        test_vm.add_watchpoint(Watchpoint::HeapRead(2..3));
+        let id = test_vm.add_watchpoint(Watchpoint::HeapWrite(4..5));
+        let event = WatchEvent::HeapRead { address: 0, len: 3 };
+        let hit = WatchHit { id: 0, pc: 4, event };
+        assert_eq!(test_vm.run_until_breakpoint(), StopReason::Watchpoint(hit));
+
+        test_vm.clear_watchpoints();
+        test_vm.pc = 4;
+        test_vm.add_watchpoint(Watchpoint::HeapWrite(12..13));
+        let event = WatchEvent::HeapWrite { address: 8, len: 5 };
+        let hit = WatchHit { id: id + 1, pc: 4, event };
+        assert_eq!(test_vm.run_until_breakpoint(), StopReason::Watchpoint(hit));
+    }
+
+    #[test]
+    fn test_watchpoint_sees_native_writes() {
+        let mut test_vm = get_test_vm();
+        test_vm.register_native("poke", |vm, _| {
+            vm.registers[7] = 1;
+            Ok(0)
+        });
+        test_vm.program = Assembler::for_vm(&test_vm).assemble("calln @poke\nhlt").unwrap();
+        test_vm.add_watchpoint(Watchpoint::Register(7));
+        assert!(matches!(test_vm.run_until_breakpoint(), StopReason::Watchpoint(_)));
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut test_vm = get_test_vm();
+        test_vm.enable_history(2, 100);
+        test_vm.registers[2] = 7;
+        test_vm.program = Assembler::new()
+            .assemble("load $0 #3\ndiv $2 $0 $1\nldstr @s $3\nloadf $1 #1.5\neq $0 #3\nhlt\ns: .asciiz 'hi'")
+            .unwrap();
+        test_vm.ro_data = b"hi\0".to_vec();
+        test_vm.run();
+        assert_eq!(test_vm.history_len(), 6);
+        assert!(test_vm.equal_flag);
+
+        assert_eq!(test_vm.reverse_step(2), StopReason::Stepped);
+        assert!(!test_vm.equal_flag);
+        assert_eq!(test_vm.float_registers[1], 1.5);
+        assert_eq!(test_vm.reverse_step(2), StopReason::Stepped);
+        assert_eq!(test_vm.heap.len(), 0);
+        assert_eq!(test_vm.registers[3], 0);
+        assert_eq!(test_vm.float_registers[1], 0.0);
+        assert_eq!(test_vm.registers[1], 2);
+        assert_eq!(test_vm.remainder, 1);
+        assert_eq!(test_vm.pc, 8);
+        assert_eq!(test_vm.reverse_step(5), StopReason::StartOfHistory);
+        assert_eq!(test_vm.pc, 0);
+        assert_eq!(test_vm.registers[0], 0);
+        assert_eq!(test_vm.remainder, 0);
+
+        // Running again after going back records the instructions again
+        test_vm.run();
+        assert_eq!(test_vm.history_len(), 6);
+        assert_eq!(test_vm.heap, b"hi\0");
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        let mut test_vm = get_test_vm();
+        test_vm.enable_history(3, 100);
+        test_vm.program = Assembler::new()
+            .assemble("load $0 #5\nloop: subi $0 #1 $0\neq $0 #0\njneq @loop\nhlt")
+            .unwrap();
+        test_vm.run();
+        test_vm.add_breakpoint(4);
+        assert_eq!(test_vm.reverse_continue(), StopReason::Breakpoint(4));
+        assert_eq!(test_vm.registers[0], 1);
+        assert_eq!(test_vm.reverse_continue(), StopReason::Breakpoint(4));
+        assert_eq!(test_vm.registers[0], 2);
+        test_vm.clear_breakpoints();
+        assert_eq!(test_vm.reverse_continue(), StopReason::StartOfHistory);
+        assert_eq!(test_vm.pc, 0);
+        assert_eq!(test_vm.registers[0], 0);
+    }
+
+    #[test]
+    fn test_reverse_without_history() {
+        let mut test_vm = get_test_vm();
+        test_vm.program = vec![1, 0, 0, 5];
+        test_vm.run_once();
+        assert_eq!(test_vm.history_len(), 0);
+        assert_eq!(test_vm.reverse_step(1), StopReason::StartOfHistory);
+        assert_eq!(test_vm.reverse_continue(), StopReason::StartOfHistory);
+    }
+
+    #[test]
+    fn test_poke_register() {
+        let mut test_vm = get_test_vm();
+        test_vm.enable_history(4, 100);
+        test_vm.program = vec![1, 0, 0, 5];
+        test_vm.run_once();
+        test_vm.poke_register(0, 9);
+        test_vm.poke_float_register(1, 0.5);
+        assert_eq!(test_vm.registers[0], 9);
+        assert_eq!(test_vm.float_registers[1], 0.5);
+        assert_eq!(test_vm.history_len(), 0);
+    }
+
+    #[test]
+    fn test_output_is_redirected() {
+        let mut test_vm = get_test_vm();
+        let output = SharedBuffer::new();
+        test_vm.set_output(Box::new(output.clone()));
+        test_vm.ro_data = b"hi\0".to_vec();
+        test_vm.program = vec![62, 0, 0, 0];
+        test_vm.run();
+        assert_eq!(output.contents(), "hiHLT encountered\n");
+    }
+
+    #[test]
+    fn test_display_program_as_hex() {
+        let mut test_vm = get_test_vm();
+        let output = SharedBuffer::new();
+        test_vm.set_output(Box::new(output.clone()));
+        test_vm.program = vec![1, 0, 1, 244, 0];
+        test_vm.display_program_as_hex().unwrap();
+        assert_eq!(output.contents(), "01 00 01 F4 \n00 ");
+    }
+
+    #[test]
+    fn test_aloc_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 1024;
+        test_vm.program = vec![17, 0, 0, 0];
+        test_vm.run_once();
+        assert_eq!(test_vm.heap.len(), 1024);
+    }
+
+    #[test]
+    fn test_and_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 0b1100;
+        test_vm.registers[1] = 0b1010;
+        test_vm.program = vec![18, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 0b1000);
+    }
+
+    #[test]
+    fn test_or_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 0b1100;
+        test_vm.registers[1] = 0b1010;
+        test_vm.program = vec![19, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 0b1110);
+    }
+
+    #[test]
+    fn test_xor_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 0b1100;
+        test_vm.registers[1] = 0b1010;
+        test_vm.program = vec![20, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 0b0110);
+    }
+
+    #[test]
+    fn test_not_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 0;
+        test_vm.program = vec![21, 0, 1];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[1], -1);
+        assert_eq!(test_vm.pc, 3);
+    }
+
+    #[test]
+    fn test_shl_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = 3;
+        test_vm.registers[1] = 4;
+        test_vm.program = vec![22, 0, 1, 2, 22, 0, 3, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 48);
+        test_vm.registers[3] = 33;
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 6);
+    }
+
+    #[test]
+    fn test_shr_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = -16;
+        test_vm.registers[1] = 28;
+        test_vm.program = vec![23, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], 0xF);
+    }
+
+    #[test]
+    fn test_sar_opcode() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[0] = -16;
+        test_vm.registers[1] = 2;
+        test_vm.program = vec![24, 0, 1, 2];
+        test_vm.run_once();
+        assert_eq!(test_vm.registers[2], -4);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+use super::{HaltReason, VM};
+use crate::instruction::Opcode;
+
+/// Watches a VM execute. Every method has an empty default, so observers only implement
+/// the events they care about. When no observer is installed none of the events are built.
+///
+/// `before_instruction`, `after_instruction` and `halt` are handed the VM so the observer
+/// can look at its state; the other events fire in the middle of an instruction and only
+/// carry what changed.
+pub trait VmObserver: fmt::Debug {
+    /// Called before the instruction at `pc` runs.
+    fn before_instruction(&mut self, _vm: &VM, _pc: usize, _opcode: Opcode) {}
+
+    /// Called once the instruction at `pc` has run, even if it stopped the VM.
+    fn after_instruction(&mut self, _vm: &VM, _pc: usize, _opcode: Opcode) {}
+
+    fn register_write(&mut self, _register: usize, _old: i32, _new: i32) {}
+
+    fn float_register_write(&mut self, _register: usize, _old: f64, _new: f64) {}
+
+    /// Called when `len` bytes of the heap starting at `address` are read.
+    fn heap_read(&mut self, _address: usize, _len: usize) {}
+
+    /// Called when `bytes` are written to the heap at `address`.
+    fn heap_write(&mut self, _address: usize, _bytes: &[u8]) {}
+
+    /// Called when the instruction at `from` jumps to `to`.
+    fn jump(&mut self, _from: usize, _to: usize) {}
+
+    fn halt(&mut self, _vm: &VM, _reason: &HaltReason) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::assembler::Assembler;
+
+    #[derive(Debug, Default)]
+    struct EventLog {
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl VmObserver for EventLog {
+        fn before_instruction(&mut self, _vm: &VM, pc: usize, opcode: Opcode) {
+            self.events.borrow_mut().push(format!("before {} {:?}", pc, opcode));
+        }
+
+        fn register_write(&mut self, register: usize, old: i32, new: i32) {
+            self.events.borrow_mut().push(format!("${} {} -> {}", register, old, new));
+        }
+
+        fn heap_read(&mut self, address: usize, len: usize) {
+            self.events.borrow_mut().push(format!("read {} {}", address, len));
+        }
+
+        fn heap_write(&mut self, address: usize, bytes: &[u8]) {
+            self.events.borrow_mut().push(format!("write {} {:?}", address, bytes));
+        }
+
+        fn jump(&mut self, from: usize, to: usize) {
+            self.events.borrow_mut().push(format!("jump {} -> {}", from, to));
+        }
+
+        fn halt(&mut self, vm: &VM, reason: &HaltReason) {
+            self.events.borrow_mut().push(format!("halt {:?} at {}", reason, vm.pc()));
+        }
+    }
+
+    #[test]
+    fn test_stop_trace_keeps_other_observers() {
+        let mut vm = VM::new();
+        let log = EventLog::default();
+        let events = Rc::clone(&log.events);
+        vm.set_observer(Some(Box::new(log)));
+        assert!(!vm.stop_trace());
+        vm.set_output(Box::new(crate::vm::output::SharedBuffer::new()));
+        vm.program = vec![0];
+        vm.run();
+        assert_eq!(*events.borrow(), vec!["before 0 HLT", "halt Hlt at 1"]);
+
+        let path = std::env::temp_dir().join(format!("vanadium_observer_{}.vtrc", std::process::id()));
+        vm.start_trace(&path).unwrap();
+        assert!(vm.stop_trace());
+        assert!(vm.take_observer().is_none());
+        assert!(!vm.stop_trace());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_observer_sees_events() {
+        let mut vm = VM::new();
+        let log = EventLog::default();
+        let events = Rc::clone(&log.events);
+        vm.set_observer(Some(Box::new(log)));
+        vm.ro_data = b"a\0".to_vec();
+        vm.program = Assembler::new()
+            .assemble("load $0 #7\nldstr @s $1\nstrlen $1 $2\njmp @end\nend: hlt\ns: .asciiz 'a'")
+            .unwrap();
+        vm.run();
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                "before 0 LOAD",
+                "$0 0 -> 7",
+                "before 4 LDSTR",
+                "write 0 [97, 0]",
+                "$1 0 -> 0",
+                "before 8 STRLEN",
+                "read 0 2",
+                "$2 0 -> 1",
+                "before 11 JMPR",
+                "jump 11 -> 14",
+                "before 14 HLT",
+                "halt Hlt at 15",
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Where the VM writes program output and diagnostics. Defaults to stdout.
+pub struct OutputSink(Box<dyn Write>);
+
+impl OutputSink {
+    pub fn new(writer: Box<dyn Write>) -> OutputSink {
+        OutputSink(writer)
+    }
+}
+
+impl Default for OutputSink {
+    fn default() -> Self {
+        OutputSink::new(Box::new(io::stdout()))
+    }
+}
+
+impl fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OutputSink")
+    }
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// An in-memory sink that stays readable after a clone of it has been handed to a VM, for
+/// capturing output in tests or forwarding it somewhere else.
+#[derive(Debug, Default, Clone)]
+pub struct SharedBuffer {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> SharedBuffer {
+        SharedBuffer::default()
+    }
+
+    /// Removes and returns everything written so far.
+    pub fn take(&self) -> Vec<u8> {
+        self.buffer.borrow_mut().split_off(0)
+    }
+
+    /// Everything written so far, lossily decoded as UTF-8.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buffer.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that can be cloned, every clone writing to the same underlying writer in
+/// order. Lets a VM print to the same place as the program embedding it.
+#[derive(Clone)]
+pub struct SharedWriter {
+    writer: Rc<RefCell<Box<dyn Write>>>,
+}
+
+impl SharedWriter {
+    pub fn new(writer: Box<dyn Write>) -> SharedWriter {
+        SharedWriter {
+            writer: Rc::new(RefCell::new(writer)),
+        }
+    }
+}
+
+impl fmt::Debug for SharedWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedWriter")
+    }
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_buffer() {
+        let buffer = SharedBuffer::new();
+        let mut sink = OutputSink::new(Box::new(buffer.clone()));
+        write!(sink, "hello {}", 42).unwrap();
+        assert_eq!(buffer.contents(), "hello 42");
+        assert_eq!(buffer.take(), b"hello 42");
+        assert_eq!(buffer.contents(), "");
+    }
+
+    #[test]
+    fn test_shared_writer_keeps_order() {
+        let buffer = SharedBuffer::new();
+        let mut first = SharedWriter::new(Box::new(buffer.clone()));
+        let mut second = first.clone();
+        write!(first, "a").unwrap();
+        write!(second, "b").unwrap();
+        write!(first, "c").unwrap();
+        assert_eq!(buffer.contents(), "abc");
+    }
+}
@@ -0,0 +1,259 @@
+//! Saving the state of a VM to bytes and loading it back.
+//!
+//! An image starts with `VSNP` and a 16 bit version, then holds, all numbers big-endian:
+//! the 32 integer registers as `i32`, the 32 float registers as `f64`, the pc as `u64`,
+//! the remainder as `i32`, the equal flag as a `u8`, the exit code as a `u8` presence flag
+//! followed by an `i32`, the program, the read-only data and the heap each as a `u32` length
+//! and their bytes, and finally a `u16` count of native names, each a `u16` length and UTF-8
+//! text.
+
+use std::fmt;
+
+use super::VM;
+
+const MAGIC: &[u8; 4] = b"VSNP";
+const VERSION: u16 = 1;
+
+/// Why an image can't be restored.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SnapshotError {
+    NotASnapshot,
+    UnsupportedVersion(u16),
+    Truncated,
+    /// The image has bytes left over after the last field.
+    TrailingData,
+    InvalidNativeName,
+    /// The program calls a native the restoring VM doesn't have at the same index.
+    MissingNative(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "Not a VM snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported snapshot version {}", version)
+            }
+            SnapshotError::Truncated => write!(f, "Snapshot is truncated"),
+            SnapshotError::TrailingData => write!(f, "Snapshot has trailing data"),
+            SnapshotError::InvalidNativeName => write!(f, "Snapshot has an invalid native name"),
+            SnapshotError::MissingNative(name) => {
+                write!(f, "Native function `{}` is not registered at the same index", name)
+            }
+        }
+    }
+}
+
+impl VM {
+    /// Saves the machine state, everything a program can observe, to a versioned image.
+    /// Host configuration such as the output, syscall handler, observer, breakpoints and
+    /// watchpoints isn't part of it; natives are saved by name only.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut image = MAGIC.to_vec();
+        image.extend_from_slice(&VERSION.to_be_bytes());
+        for value in self.registers.iter() {
+            image.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in self.float_registers.iter() {
+            image.extend_from_slice(&value.to_be_bytes());
+        }
+        image.extend_from_slice(&(self.pc as u64).to_be_bytes());
+        image.extend_from_slice(&self.remainder.to_be_bytes());
+        image.push(self.equal_flag as u8);
+        image.push(self.exit_code.is_some() as u8);
+        image.extend_from_slice(&self.exit_code.unwrap_or(0).to_be_bytes());
+        for bytes in [&self.program, &self.ro_data, &self.heap].iter() {
+            image.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            image.extend_from_slice(bytes);
+        }
+        image.extend_from_slice(&(self.natives.len() as u16).to_be_bytes());
+        for (name, _) in &self.natives {
+            image.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            image.extend_from_slice(name.as_bytes());
+        }
+        image
+    }
+
+    /// Replaces the machine state with the one saved in `image`, leaving the VM untouched if
+    /// the image can't be restored. The natives named in the image must already be
+    /// registered on this VM, at the same indices, for the program's `calln`s to keep
+    /// calling the same functions. The reverse debugging history is cleared.
+    pub fn restore(&mut self, image: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader { image };
+        if reader.take(4)? != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut registers = [0; 32];
+        for value in registers.iter_mut() {
+            *value = reader.i32()?;
+        }
+        let mut float_registers = [0.0; 32];
+        for value in float_registers.iter_mut() {
+            *value = f64::from_bits(reader.u64()?);
+        }
+        let pc = reader.u64()? as usize;
+        let remainder = reader.i32()?;
+        let equal_flag = reader.u8()? != 0;
+        let has_exit_code = reader.u8()? != 0;
+        let exit_code = Some(reader.i32()?).filter(|_| has_exit_code);
+        let program = reader.bytes()?;
+        let ro_data = reader.bytes()?;
+        let heap = reader.bytes()?;
+        for index in 0..reader.u16()? as usize {
+            let len = reader.u16()? as usize;
+            let name = std::str::from_utf8(reader.take(len)?)
+                .map_err(|_| SnapshotError::InvalidNativeName)?;
+            match self.natives.get(index) {
+                Some((registered, _)) if registered == name => {}
+                _ => return Err(SnapshotError::MissingNative(name.to_string())),
+            }
+        }
+        if !reader.image.is_empty() {
+            return Err(SnapshotError::TrailingData);
+        }
+
+        self.registers = registers;
+        self.float_registers = float_registers;
+        self.pc = pc;
+        self.remainder = remainder;
+        self.equal_flag = equal_flag;
+        self.exit_code = exit_code;
+        self.program = program;
+        self.ro_data = ro_data;
+        self.heap = heap;
+        self.forget_history();
+        Ok(())
+    }
+}
+
+/// Reads the fields of an image from its start.
+struct Reader<'a> {
+    image: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.image.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (bytes, rest) = self.image.split_at(len);
+        self.image = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(bytes))
+    }
+
+    fn i32(&mut self) -> Result<i32, SnapshotError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(i32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        let len = u32::from_be_bytes(bytes) as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::vm::output::SharedBuffer;
+
+    const COUNTDOWN: &str = "load $0 #6\nldstr @s $5\nloadf $2 #0.5\n\
+        loop: subi $0 #1 $0\nmul $0 $0 $1\neq $0 #0\njneq @loop\nprth $5\nhlt\ns: .asciiz 'done'";
+
+    fn countdown_vm() -> (VM, SharedBuffer) {
+        let mut vm = VM::new();
+        let output = SharedBuffer::new();
+        vm.set_output(Box::new(output.clone()));
+        let mut assembler = Assembler::new();
+        vm.program = assembler.assemble(COUNTDOWN).unwrap();
+        vm.ro_data = assembler.ro_data().to_vec();
+        (vm, output)
+    }
+
+    #[test]
+    fn test_restore_resumes_exactly() {
+        let (mut original, original_output) = countdown_vm();
+        original.step(7);
+        let image = original.snapshot();
+
+        let mut resumed = VM::new();
+        let resumed_output = SharedBuffer::new();
+        resumed.set_output(Box::new(resumed_output.clone()));
+        resumed.restore(&image).unwrap();
+        assert_eq!(resumed.snapshot(), image);
+
+        original.run();
+        resumed.run();
+        assert_eq!(resumed.snapshot(), original.snapshot());
+        assert_eq!(resumed_output.contents(), original_output.contents());
+        assert_eq!(resumed.registers[0], 0);
+        assert_eq!(resumed.float_registers[2], 0.5);
+    }
+
+    #[test]
+    fn test_snapshot_keeps_flags() {
+        let mut vm = VM::new();
+        vm.program = vec![1, 0, 0, 7, 1, 1, 0, 2, 5, 0, 1, 2, 9, 0, 0];
+        vm.run();
+        let mut restored = VM::new();
+        restored.restore(&vm.snapshot()).unwrap();
+        assert_eq!(restored.remainder(), 1);
+        assert!(restored.equal_flag());
+        assert_eq!(restored.pc(), vm.pc());
+        assert_eq!(restored.exit_code(), None);
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_images() {
+        let (vm, _) = countdown_vm();
+        let image = vm.snapshot();
+        let mut target = VM::new();
+        assert_eq!(target.restore(b"nope"), Err(SnapshotError::NotASnapshot));
+        assert_eq!(target.restore(&image[..image.len() - 1]), Err(SnapshotError::Truncated));
+        let mut newer = image.clone();
+        newer[5] = 2;
+        assert_eq!(target.restore(&newer), Err(SnapshotError::UnsupportedVersion(2)));
+        let mut longer = image.clone();
+        longer.push(0);
+        assert_eq!(target.restore(&longer), Err(SnapshotError::TrailingData));
+        assert_eq!(target.program, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_restore_checks_natives() {
+        let mut vm = VM::new();
+        vm.register_native("one", |_, _| Ok(1));
+        let image = vm.snapshot();
+
+        let mut target = VM::new();
+        let missing = SnapshotError::MissingNative("one".to_string());
+        assert_eq!(target.restore(&image), Err(missing));
+        target.register_native("one", |_, _| Ok(1));
+        target.register_native("two", |_, _| Ok(2));
+        assert_eq!(target.restore(&image), Ok(()));
+    }
+}
@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::rc::Rc;
+use std::time::Instant;
+
+use super::{VmError, VM};
+
+/// Writes the heap string whose address is in `$1` to the VM's output and stores the number of
+/// bytes written in `$0`.
+pub const SYS_WRITE: u16 = 0;
+/// Reads a line, without its line terminator, into a new heap string whose address is stored in `$0`.
+pub const SYS_READ_LINE: u16 = 1;
+/// Stores the number of milliseconds elapsed since the handler was created in `$0`.
+pub const SYS_CLOCK: u16 = 2;
+/// Stops the VM with the exit code in `$1`.
+pub const SYS_EXIT: u16 = 3;
+
+/// What the VM should do once a syscall returns.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SyscallOutcome {
+    Continue,
+    Exit(i32),
+}
+
+/// Services the `SYSCALL #<number>` instruction. Arguments are passed in registers `$1` and
+/// up, and results are returned in `$0`.
+///
+/// The handler is the only way a program can reach outside of the VM, so an embedding
+/// application decides what a script can do by choosing which handler to install.
+pub trait SyscallHandler: fmt::Debug {
+    fn syscall(&mut self, number: u16, vm: &mut VM) -> Result<SyscallOutcome, VmError>;
+}
+
+/// Where `SYS_READ_LINE` reads from. Defaults to stdin; clones share the same reader, so a
+/// REPL and the programs it runs can consume one input stream.
+#[derive(Clone, Default)]
+pub struct InputSource(Option<Rc<RefCell<Box<dyn BufRead>>>>);
+
+impl InputSource {
+    pub fn new(reader: Box<dyn BufRead>) -> InputSource {
+        InputSource(Some(Rc::new(RefCell::new(reader))))
+    }
+
+    /// Appends the next line, with its line terminator, to `line` and returns the number of
+    /// bytes read, 0 at the end of the input.
+    pub fn read_line(&self, line: &mut String) -> io::Result<usize> {
+        match &self.0 {
+            Some(reader) => reader.borrow_mut().read_line(line),
+            None => io::stdin().lock().read_line(line),
+        }
+    }
+}
+
+impl fmt::Debug for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InputSource")
+    }
+}
+
+/// Handler installed by `VM::new`, reading from its input source, stdin unless given another
+/// one, and writing to the VM's output, which is stdout unless redirected.
+#[derive(Debug)]
+pub struct DefaultSyscalls {
+    started: Instant,
+    input: InputSource,
+}
+
+impl Default for DefaultSyscalls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DefaultSyscalls {
+    pub fn new() -> DefaultSyscalls {
+        DefaultSyscalls::with_input(InputSource::default())
+    }
+
+    pub fn with_input(input: InputSource) -> DefaultSyscalls {
+        DefaultSyscalls {
+            started: Instant::now(),
+            input,
+        }
+    }
+}
+
+impl SyscallHandler for DefaultSyscalls {
+    fn syscall(&mut self, number: u16, vm: &mut VM) -> Result<SyscallOutcome, VmError> {
+        match number {
+            SYS_WRITE => {
+                let string = vm.read_heap_string(vm.registers[1] as usize).to_vec();
+                vm.write_output(&string)?;
+                vm.registers[0] = string.len() as i32;
+            }
+            SYS_READ_LINE => {
+                let mut line = String::new();
+                self.input.read_line(&mut line)?;
+                let line = line.trim_end_matches(['\n', '\r']);
+                vm.registers[0] = vm.alloc_string(line.as_bytes());
+            }
+            SYS_CLOCK => {
+                vm.registers[0] = self.started.elapsed().as_millis() as i32;
+            }
+            SYS_EXIT => return Ok(SyscallOutcome::Exit(vm.registers[1])),
+            _ => return Err(VmError::UnknownSyscall(number)),
+        }
+        Ok(SyscallOutcome::Continue)
+    }
+}
+
+/// A syscall seen by `RecordingSyscalls`, with the argument registers `$1` to `$3` at the time.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyscallRecord {
+    pub number: u16,
+    pub args: [i32; 3],
+}
+
+/// Handler for tests: records every syscall instead of performing it and stores canned results
+/// in `$0`. `SYS_EXIT` still stops the VM so programs terminate the same way.
+#[derive(Debug, Default)]
+pub struct RecordingSyscalls {
+    calls: Rc<RefCell<Vec<SyscallRecord>>>,
+    results: VecDeque<i32>,
+}
+
+impl RecordingSyscalls {
+    pub fn new() -> RecordingSyscalls {
+        RecordingSyscalls::default()
+    }
+
+    /// Queues the value stored in `$0` by the next recorded syscall. Once the queue is empty
+    /// `$0` is left untouched.
+    pub fn push_result(&mut self, value: i32) {
+        self.results.push_back(value);
+    }
+
+    /// Shared handle on the recorded calls, which stays readable after the handler has been
+    /// moved into a VM.
+    pub fn calls(&self) -> Rc<RefCell<Vec<SyscallRecord>>> {
+        Rc::clone(&self.calls)
+    }
+}
+
+impl SyscallHandler for RecordingSyscalls {
+    fn syscall(&mut self, number: u16, vm: &mut VM) -> Result<SyscallOutcome, VmError> {
+        self.calls.borrow_mut().push(SyscallRecord {
+            number,
+            args: [vm.registers[1], vm.registers[2], vm.registers[3]],
+        });
+        if let Some(value) = self.results.pop_front() {
+            vm.registers[0] = value;
+        }
+        if number == SYS_EXIT {
+            return Ok(SyscallOutcome::Exit(vm.registers[1]));
+        }
+        Ok(SyscallOutcome::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::output::SharedBuffer;
+
+    #[test]
+    fn test_default_clock_and_exit() {
+        let mut handler = DefaultSyscalls::new();
+        let mut vm = VM::new();
+        vm.registers[0] = -1;
+        assert_eq!(handler.syscall(SYS_CLOCK, &mut vm), Ok(SyscallOutcome::Continue));
+        assert!(vm.registers[0] >= 0);
+        vm.registers[1] = 7;
+        assert_eq!(handler.syscall(SYS_EXIT, &mut vm), Ok(SyscallOutcome::Exit(7)));
+        assert_eq!(handler.syscall(99, &mut vm), Err(VmError::UnknownSyscall(99)));
+    }
+
+    #[test]
+    fn test_default_write() {
+        let mut handler = DefaultSyscalls::new();
+        let mut vm = VM::new();
+        let output = SharedBuffer::new();
+        vm.set_output(Box::new(output.clone()));
+        vm.registers[1] = vm.alloc_string(b"hello");
+        assert_eq!(handler.syscall(SYS_WRITE, &mut vm), Ok(SyscallOutcome::Continue));
+        assert_eq!(vm.registers[0], 5);
+        assert_eq!(output.contents(), "hello");
+    }
+
+    #[test]
+    fn test_default_read_line() {
+        let input = InputSource::new(Box::new(&b"first\r\nsecond"[..]));
+        let mut handler = DefaultSyscalls::with_input(input.clone());
+        let mut vm = VM::new();
+        assert_eq!(handler.syscall(SYS_READ_LINE, &mut vm), Ok(SyscallOutcome::Continue));
+        assert_eq!(vm.read_heap_string(vm.registers[0] as usize), b"first");
+        let mut rest = String::new();
+        assert_eq!(input.read_line(&mut rest).unwrap(), 6);
+        assert_eq!(rest, "second");
+    }
+
+    #[test]
+    fn test_recording_syscalls() {
+        let mut handler = RecordingSyscalls::new();
+        handler.push_result(42);
+        let calls = handler.calls();
+        let mut vm = VM::new();
+        vm.registers[1] = 1;
+        vm.registers[2] = 2;
+        assert_eq!(handler.syscall(SYS_WRITE, &mut vm), Ok(SyscallOutcome::Continue));
+        assert_eq!(vm.registers[0], 42);
+        assert_eq!(
+            *calls.borrow(),
+            vec![SyscallRecord { number: SYS_WRITE, args: [1, 2, 0] }]
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! Recording of executed instructions to a trace file, and replay of those traces.
+//!
+//! A trace starts with `VTRC` and a 16 bit version, followed by tagged records, all
+//! numbers big-endian:
+//!
+//! * `0`: the registers when recording started, 32 `i32` then 32 `f64`.
+//! * `1`: an executed instruction: its `u32` pc, its disassembly as a `u16` length and
+//!   UTF-8 text, then a `u8` count of register changes. Each change is a `u8` kind
+//!   (`0` integer, `1` float), the `u8` register, and its old and new values.
+//! * `2`: the VM halted after the previous instruction, with the reason as text.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::observer::VmObserver;
+use super::{HaltReason, VM};
+use crate::assembler::disassembler::disassemble;
+use crate::instruction::Opcode;
+
+const MAGIC: &[u8; 4] = b"VTRC";
+const VERSION: u16 = 1;
+
+const TAG_INITIAL: u8 = 0;
+const TAG_STEP: u8 = 1;
+const TAG_HALT: u8 = 2;
+
+/// The value a register held before and after an instruction.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RegisterChange {
+    Integer { register: u8, old: i32, new: i32 },
+    Float { register: u8, old: f64, new: f64 },
+}
+
+/// One executed instruction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceStep {
+    pub pc: usize,
+    /// The instruction, disassembled.
+    pub instruction: String,
+    /// Register writes in the order the instruction made them.
+    pub changes: Vec<RegisterChange>,
+    /// Why the VM stopped after this instruction, if it did.
+    pub halt: Option<String>,
+}
+
+/// Observer writing a trace of everything the VM executes. Install it with
+/// [`VM::start_trace`], or with [`VM::set_observer`] for a writer that isn't a file.
+///
+/// Observers can't report errors, so the first write that fails ends the recording.
+pub struct TraceRecorder {
+    writer: Option<BufWriter<Box<dyn Write>>>,
+    changes: Vec<RegisterChange>,
+}
+
+impl fmt::Debug for TraceRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceRecorder")
+            .field("recording", &self.writer.is_some())
+            .field("changes", &self.changes)
+            .finish()
+    }
+}
+
+impl TraceRecorder {
+    /// Starts a trace in `writer` with the current registers of `vm`.
+    pub fn new(writer: Box<dyn Write>, vm: &VM) -> io::Result<TraceRecorder> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_be_bytes())?;
+        writer.write_all(&[TAG_INITIAL])?;
+        for value in vm.registers.iter() {
+            writer.write_all(&value.to_be_bytes())?;
+        }
+        for value in vm.float_registers.iter() {
+            writer.write_all(&value.to_be_bytes())?;
+        }
+        Ok(TraceRecorder {
+            writer: Some(writer),
+            changes: vec![],
+        })
+    }
+
+    fn record<F>(&mut self, write: F)
+    where
+        F: FnOnce(&mut BufWriter<Box<dyn Write>>) -> io::Result<()>,
+    {
+        if let Some(writer) = self.writer.as_mut() {
+            if write(writer).is_err() {
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl VmObserver for TraceRecorder {
+    fn before_instruction(&mut self, _vm: &VM, _pc: usize, _opcode: Opcode) {
+        self.changes.clear();
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: usize, opcode: Opcode) {
+        let instruction = disassemble(&vm.program, pc, &vm.native_names())
+            .map(|instruction| instruction.to_string())
+            .unwrap_or_else(|| opcode.mnemonic().to_string());
+        let changes = std::mem::take(&mut self.changes);
+        self.record(|writer| {
+            writer.write_all(&[TAG_STEP])?;
+            writer.write_all(&(pc as u32).to_be_bytes())?;
+            write_text(writer, &instruction)?;
+            writer.write_all(&[changes.len() as u8])?;
+            for change in &changes {
+                match *change {
+                    RegisterChange::Integer { register, old, new } => {
+                        writer.write_all(&[0, register])?;
+                        writer.write_all(&old.to_be_bytes())?;
+                        writer.write_all(&new.to_be_bytes())?;
+                    }
+                    RegisterChange::Float { register, old, new } => {
+                        writer.write_all(&[1, register])?;
+                        writer.write_all(&old.to_be_bytes())?;
+                        writer.write_all(&new.to_be_bytes())?;
+                    }
+                }
+            }
+            Ok(())
+        });
+    }
+
+    fn register_write(&mut self, register: usize, old: i32, new: i32) {
+        let register = register as u8;
+        self.changes.push(RegisterChange::Integer { register, old, new });
+    }
+
+    fn float_register_write(&mut self, register: usize, old: f64, new: f64) {
+        let register = register as u8;
+        self.changes.push(RegisterChange::Float { register, old, new });
+    }
+
+    fn halt(&mut self, _vm: &VM, reason: &HaltReason) {
+        let reason = format!("{:?}", reason);
+        self.record(|writer| {
+            writer.write_all(&[TAG_HALT])?;
+            write_text(writer, &reason)?;
+            writer.flush()
+        });
+    }
+}
+
+fn write_text(writer: &mut dyn Write, text: &str) -> io::Result<()> {
+    let bytes = &text.as_bytes()[..text.len().min(u16::MAX as usize)];
+    writer.write_all(&(bytes.len() as u16).to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// A recorded trace, stepped through forwards and backwards by applying the register
+/// changes of each instruction instead of executing it again.
+#[derive(Debug)]
+pub struct Replay {
+    steps: Vec<TraceStep>,
+    position: usize,
+    registers: [i32; 32],
+    float_registers: [f64; 32],
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        Replay::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Replay> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a trace file"));
+        }
+        if read_u16(&mut reader)? != VERSION {
+            return Err(invalid("unsupported trace version"));
+        }
+        if read_u8(&mut reader)? != TAG_INITIAL {
+            return Err(invalid("trace doesn't start with the initial registers"));
+        }
+        let mut registers = [0; 32];
+        for value in registers.iter_mut() {
+            *value = read_i32(&mut reader)?;
+        }
+        let mut float_registers = [0.0; 32];
+        for value in float_registers.iter_mut() {
+            *value = read_f64(&mut reader)?;
+        }
+
+        let mut steps: Vec<TraceStep> = vec![];
+        loop {
+            let mut tag = [0];
+            if reader.read(&mut tag)? == 0 {
+                break;
+            }
+            match tag[0] {
+                TAG_STEP => {
+                    let pc = read_u32(&mut reader)? as usize;
+                    let instruction = read_text(&mut reader)?;
+                    let count = read_u8(&mut reader)?;
+                    let mut changes = vec![];
+                    for _ in 0..count {
+                        let kind = read_u8(&mut reader)?;
+                        let register = read_u8(&mut reader)?;
+                        if register >= 32 {
+                            return Err(invalid("register out of range"));
+                        }
+                        changes.push(match kind {
+                            0 => RegisterChange::Integer {
+                                register,
+                                old: read_i32(&mut reader)?,
+                                new: read_i32(&mut reader)?,
+                            },
+                            1 => RegisterChange::Float {
+                                register,
+                                old: read_f64(&mut reader)?,
+                                new: read_f64(&mut reader)?,
+                            },
+                            _ => return Err(invalid("unknown register kind")),
+                        });
+                    }
+                    steps.push(TraceStep {
+                        pc,
+                        instruction,
+                        changes,
+                        halt: None,
+                    });
+                }
+                TAG_HALT => {
+                    let reason = read_text(&mut reader)?;
+                    // A halt before any instruction ran changes nothing worth replaying
+                    if let Some(step) = steps.last_mut() {
+                        step.halt = Some(reason);
+                    }
+                }
+                _ => return Err(invalid("unknown record")),
+            }
+        }
+
+        Ok(Replay {
+            steps,
+            position: 0,
+            registers,
+            float_registers,
+        })
+    }
+
+    /// Number of instructions in the trace.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Number of instructions replayed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+
+    /// The instruction the next `step_forward` replays.
+    pub fn next_step(&self) -> Option<&TraceStep> {
+        self.steps.get(self.position)
+    }
+
+    /// The registers as they were after the instructions replayed so far.
+    pub fn registers(&self) -> &[i32; 32] {
+        &self.registers
+    }
+
+    pub fn float_registers(&self) -> &[f64; 32] {
+        &self.float_registers
+    }
+
+    /// Replays the next instruction and returns it, or `None` at the end of the trace.
+    pub fn step_forward(&mut self) -> Option<&TraceStep> {
+        let step = self.steps.get(self.position)?;
+        for change in &step.changes {
+            match *change {
+                RegisterChange::Integer { register, new, .. } => {
+                    self.registers[register as usize] = new
+                }
+                RegisterChange::Float { register, new, .. } => {
+                    self.float_registers[register as usize] = new
+                }
+            }
+        }
+        self.position += 1;
+        Some(step)
+    }
+
+    /// Undoes the last replayed instruction and returns it, or `None` at the start.
+    pub fn step_backward(&mut self) -> Option<&TraceStep> {
+        let position = self.position.checked_sub(1)?;
+        let step = &self.steps[position];
+        for change in step.changes.iter().rev() {
+            match *change {
+                RegisterChange::Integer { register, old, .. } => {
+                    self.registers[register as usize] = old
+                }
+                RegisterChange::Float { register, old, .. } => {
+                    self.float_registers[register as usize] = old
+                }
+            }
+        }
+        self.position = position;
+        Some(step)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    Ok(read_u32(reader)? as i32)
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_be_bytes(bytes))
+}
+
+fn read_text<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut bytes = vec![0; read_u16(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid("instruction text isn't UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+    use crate::vm::output::SharedBuffer;
+
+    fn record(source: &str) -> Replay {
+        let mut vm = VM::new();
+        vm.set_output(Box::new(SharedBuffer::new()));
+        vm.program = Assembler::new().assemble(source).unwrap();
+        let trace = SharedBuffer::new();
+        let recorder = TraceRecorder::new(Box::new(trace.clone()), &vm).unwrap();
+        vm.set_observer(Some(Box::new(recorder)));
+        vm.run();
+        vm.set_observer(None);
+        Replay::from_reader(&trace.take()[..]).unwrap()
+    }
+
+    #[test]
+    fn test_trace_records_steps() {
+        let replay = record("load $0 #3\nloadf $1 #2.5\nadd $0 $0 $0\nhlt");
+        let steps = replay.steps();
+        assert_eq!(replay.len(), 4);
+        assert_eq!(steps[0].pc, 0);
+        assert_eq!(steps[0].instruction, "load $0 #3");
+        assert_eq!(
+            steps[0].changes,
+            vec![RegisterChange::Integer { register: 0, old: 0, new: 3 }]
+        );
+        assert_eq!(
+            steps[1].changes,
+            vec![RegisterChange::Float { register: 1, old: 0.0, new: 2.5 }]
+        );
+        assert_eq!(steps[2].pc, 14);
+        assert_eq!(steps[3].instruction, "hlt");
+        assert!(steps[3].changes.is_empty());
+        assert_eq!(steps[3].halt, Some("Hlt".to_string()));
+    }
+
+    #[test]
+    fn test_replay_steps_both_ways() {
+        let mut replay = record("load $0 #3\nadd $0 $0 $0\nadd $0 $0 $0\nhlt");
+        assert_eq!(replay.step_backward(), None);
+        while replay.step_forward().is_some() {}
+        assert_eq!(replay.position(), 4);
+        assert_eq!(replay.registers()[0], 12);
+
+        assert_eq!(replay.step_backward().unwrap().instruction, "hlt");
+        assert_eq!(replay.step_backward().unwrap().instruction, "add $0 $0 $0");
+        assert_eq!(replay.registers()[0], 6);
+        replay.step_backward();
+        replay.step_backward();
+        assert_eq!(replay.position(), 0);
+        assert_eq!(replay.registers()[0], 0);
+        assert_eq!(replay.next_step().unwrap().instruction, "load $0 #3");
+    }
+
+    #[test]
+    fn test_replay_rejects_other_files() {
+        assert!(Replay::from_reader(&b"not a trace"[..]).is_err());
+        assert!(Replay::from_reader(&b"VTRC\x00\x01\x00"[..]).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// How a conditional watchpoint compares its register with its value.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Eq,
+    Neq,
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+}
+
+impl Comparison {
+    pub fn holds(self, left: i32, right: i32) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Neq => left != right,
+            Comparison::Gt => left > right,
+            Comparison::Lt => left < right,
+            Comparison::Gte => left >= right,
+            Comparison::Lte => left <= right,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Neq => "!=",
+            Comparison::Gt => ">",
+            Comparison::Lt => "<",
+            Comparison::Gte => ">=",
+            Comparison::Lte => "<=",
+        }
+    }
+}
+
+/// Something `step` and `run_until_breakpoint` stop on once the instruction touching it
+/// has run.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Watchpoint {
+    /// An instruction changed the value of the register.
+    Register(usize),
+    /// An instruction changed the value of the register, and the new value satisfies
+    /// `comparison` against `value`.
+    Condition {
+        register: usize,
+        comparison: Comparison,
+        value: i32,
+    },
+    /// An instruction wrote to one of these heap addresses.
+    HeapWrite(Range<usize>),
+    /// An instruction read one of these heap addresses.
+    HeapRead(Range<usize>),
+}
+
+impl Watchpoint {
+    /// Whether writing `new` over `old` in `register` triggers the watchpoint.
+    pub fn register_written(&self, written: usize, old: i32, new: i32) -> bool {
+        match *self {
+            Watchpoint::Register(register) => register == written && old != new,
+            Watchpoint::Condition {
+                register,
+                comparison,
+                value,
+            } => register == written && old != new && comparison.holds(new, value),
+            _ => false,
+        }
+    }
+
+    /// Whether reading (`write` false) or writing `len` bytes at `address` triggers the
+    /// watchpoint.
+    pub fn heap_accessed(&self, address: usize, len: usize, write: bool) -> bool {
+        let range = match self {
+            Watchpoint::HeapWrite(range) if write => range,
+            Watchpoint::HeapRead(range) if !write => range,
+            _ => return false,
+        };
+        address < range.end && range.start < address + len
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watchpoint::Register(register) => write!(f, "${}", register),
+            Watchpoint::Condition {
+                register,
+                comparison,
+                value,
+            } => write!(f, "${} {} {}", register, comparison.symbol(), value),
+            Watchpoint::HeapWrite(range) => write!(f, "write {} {}", range.start, range.len()),
+            Watchpoint::HeapRead(range) => write!(f, "read {} {}", range.start, range.len()),
+        }
+    }
+}
+
+/// Parses the form `Display` prints: `$5`, `$3 > 100`, or `write 16 4` / `read 16 4` for
+/// heap addresses 16 to 19, the length defaulting to one byte.
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let number = |word: &str| {
+            word.parse::<usize>()
+                .map_err(|_| format!("Expected a number, got `{}`", word))
+        };
+        match words.as_slice() {
+            [register] if register.starts_with('$') => Ok(Watchpoint::Register(parse_register(register)?)),
+            [register, comparison, value] if register.starts_with('$') => {
+                let comparison = match *comparison {
+                    "==" => Comparison::Eq,
+                    "!=" => Comparison::Neq,
+                    ">" => Comparison::Gt,
+                    "<" => Comparison::Lt,
+                    ">=" => Comparison::Gte,
+                    "<=" => Comparison::Lte,
+                    other => return Err(format!("Unknown comparison `{}`", other)),
+                };
+                Ok(Watchpoint::Condition {
+                    register: parse_register(register)?,
+                    comparison,
+                    value: value
+                        .parse()
+                        .map_err(|_| format!("Expected a number, got `{}`", value))?,
+                })
+            }
+            [access, address, rest @ ..] if rest.len() <= 1 => {
+                let start = number(address)?;
+                let len = match rest {
+                    [len] => number(len)?,
+                    _ => 1,
+                };
+                let end = start
+                    .checked_add(len)
+                    .ok_or_else(|| format!("The range {} {} is past the end of the heap", start, len))?;
+                let range = start..end;
+                match *access {
+                    "write" => Ok(Watchpoint::HeapWrite(range)),
+                    "read" => Ok(Watchpoint::HeapRead(range)),
+                    other => Err(format!("Expected `read` or `write`, got `{}`", other)),
+                }
+            }
+            _ => Err(format!("Unable to parse watchpoint `{}`", s)),
+        }
+    }
+}
+
+fn parse_register(word: &str) -> Result<usize, String> {
+    match word[1..].parse::<usize>() {
+        Ok(register) if register < 32 => Ok(register),
+        _ => Err(format!("Expected a register from $0 to $31, got `{}`", word)),
+    }
+}
+
+/// What triggered a watchpoint.
+#[derive(Debug, PartialEq, Clone)]
+pub enum WatchEvent {
+    RegisterWrite { register: usize, old: i32, new: i32 },
+    HeapWrite { address: usize, len: usize },
+    HeapRead { address: usize, len: usize },
+}
+
+impl fmt::Display for WatchEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchEvent::RegisterWrite { register, old, new } => {
+                write!(f, "${} changed from {} to {}", register, old, new)
+            }
+            WatchEvent::HeapWrite { address, len } => {
+                write!(f, "{} bytes written at heap address {}", len, address)
+            }
+            WatchEvent::HeapRead { address, len } => {
+                write!(f, "{} bytes read at heap address {}", len, address)
+            }
+        }
+    }
+}
+
+/// A watchpoint that triggered, with the instruction that triggered it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WatchHit {
+    /// The id `add_watchpoint` returned for the watchpoint.
+    pub id: usize,
+    pub pc: usize,
+    pub event: WatchEvent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchpoints() {
+        assert_eq!("$5".parse(), Ok(Watchpoint::Register(5)));
+        assert_eq!(
+            "$3 > 100".parse(),
+            Ok(Watchpoint::Condition {
+                register: 3,
+                comparison: Comparison::Gt,
+                value: 100
+            })
+        );
+        assert_eq!("write 16 4".parse(), Ok(Watchpoint::HeapWrite(16..20)));
+        assert_eq!("read 2".parse(), Ok(Watchpoint::HeapRead(2..3)));
+        assert!("$32".parse::<Watchpoint>().is_err());
+        assert!("$3 ~ 1".parse::<Watchpoint>().is_err());
+        assert!("poke 1 2".parse::<Watchpoint>().is_err());
+        assert!("".parse::<Watchpoint>().is_err());
+        assert_eq!(
+            "write 1 18446744073709551615".parse::<Watchpoint>(),
+            Err("The range 1 18446744073709551615 is past the end of the heap".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_parses_back() {
+        for text in &["$5", "$3 <= -2", "write 16 4", "read 0 1"] {
+            assert_eq!(text.parse::<Watchpoint>().unwrap().to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn test_triggers() {
+        let condition: Watchpoint = "$3 > 100".parse().unwrap();
+        assert!(!condition.register_written(3, 0, 100));
+        assert!(condition.register_written(3, 0, 101));
+        assert!(!condition.register_written(3, 101, 101));
+        assert!(!condition.register_written(4, 0, 101));
+
+        let heap = Watchpoint::HeapWrite(16..20);
+        assert!(heap.heap_accessed(19, 4, true));
+        assert!(heap.heap_accessed(10, 7, true));
+        assert!(!heap.heap_accessed(20, 4, true));
+        assert!(!heap.heap_accessed(16, 4, false));
+    }
+}